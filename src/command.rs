@@ -1,12 +1,153 @@
 //! OeCli Subcommand executor.
 
+use crate::error::OecliError;
 use crate::log::LogLevel;
-#[cfg(feature = "println")]
-use crate::step::msg_handler::ConsoleLogEventHandler;
-use crate::step::msg_handler::ProgressBarsEventHandler;
-use crate::step::{ExecutorProperties, StepExecutor};
+use crate::step::msg_handler::{
+    ConsoleLogEventHandler, FanOutEventHandler, JsonEventHandler, ProgressBarsEventHandler,
+};
+use crate::step::process::{CommandOutput, Invocation};
+use crate::step::{
+    DiagramFormat, ExecutorProperties, FailureMode, RunContext, RunJournal, StepExecutor,
+};
 use async_trait::async_trait;
-use std::future::Future;
+use clap::Args;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+/// Options shared by every command that is processed by the [StepExecutor]().
+#[derive(Args, Clone, Debug, Default)]
+pub struct ExecutorOptions {
+    /// Resume a previous run. Steps that completed during that run are skipped.
+    #[clap(long, global = true, value_name = "RUN_ID")]
+    pub resume: Option<String>,
+    /// Print which steps would run, be skipped or error without executing any of them.
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+    /// Interrupt any step that runs longer than this many seconds.
+    #[clap(long, global = true, value_name = "SECONDS")]
+    pub step_timeout: Option<u64>,
+    /// When a step fails, undo the steps that completed during this run in reverse order.
+    #[clap(long, global = true)]
+    pub rollback_on_failure: bool,
+    /// What happens to the other steps running alongside a step that fails.
+    #[clap(long, global = true, value_enum, default_value = "fail-fast")]
+    pub failure_mode: FailureMode,
+    /// Run at most this many steps at once.
+    #[clap(
+        long,
+        short = 'j',
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub jobs: Option<u64>,
+    /// Run at most N steps that use the resource at once, for example `--limit github-api=2`. Can
+    /// be repeated.
+    #[clap(long = "limit", global = true, value_name = "RESOURCE=N", value_parser = parse_limit)]
+    pub limits: Vec<(String, usize)>,
+    /// How progress is reported. Defaults to progress bars on a terminal and plain lines
+    /// otherwise.
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+    /// How much is reported. Defaults to info.
+    #[clap(long, global = true, value_enum, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+    /// Report more, can be repeated.
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Report less, can be repeated.
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    pub quiet: u8,
+    /// Also write every event as a line of json to this file, whatever the log level.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub events_file: Option<String>,
+    /// Once the run is over, print how long every step and sequence took, the critical path and
+    /// the time spent waiting at join points.
+    #[clap(long, global = true)]
+    pub timings: bool,
+    /// Once the run is over, write the timings as a Chrome trace to this file.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub trace_file: Option<String>,
+    /// Print the steps as a diagram in this format instead of running them.
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    pub graph: Option<DiagramFormat>,
+}
+
+/// How the progress of a run is reported.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// A progress bar per sequence.
+    Progress,
+    /// A line per event, suited for logs.
+    Plain,
+    /// A json object per event, for other tools to read.
+    Json,
+}
+
+impl ExecutorOptions {
+    /// The executor properties every command starts from.
+    fn properties(&self) -> ExecutorProperties {
+        let mut properties = ExecutorProperties::new().with_failure_mode(self.failure_mode);
+        if let Some(seconds) = self.step_timeout {
+            properties = properties.with_step_timeout(Duration::from_secs(seconds));
+        }
+        if self.rollback_on_failure {
+            properties = properties.with_rollback_on_failure();
+        }
+        if let Some(jobs) = self.jobs {
+            properties = properties.with_jobs(jobs as usize);
+        }
+        for (resource, limit) in &self.limits {
+            properties = properties.with_resource_limit(resource, *limit);
+        }
+        properties
+    }
+
+    /// The log level with every `-v` and `-q` applied.
+    fn log_level(&self) -> LogLevel {
+        self.log_level
+            .unwrap_or(LogLevel::Info)
+            .adjust(self.verbose, self.quiet)
+    }
+
+    /// The event handler matching the output format, and the events file when one is given.
+    /// Progress bars are drawn on stderr, so they are only used by default when stderr is a
+    /// terminal.
+    fn event_handler(&self) -> Result<FanOutEventHandler, OecliError> {
+        let output = self.output.unwrap_or_else(|| {
+            if std::io::stderr().is_terminal() {
+                OutputFormat::Progress
+            } else {
+                OutputFormat::Plain
+            }
+        });
+        let log_level = self.log_level();
+        let mut msg_handler = FanOutEventHandler::new();
+        match output {
+            OutputFormat::Progress => msg_handler.push(ProgressBarsEventHandler::new(log_level)),
+            OutputFormat::Plain => msg_handler.push(ConsoleLogEventHandler::new(log_level)),
+            OutputFormat::Json => msg_handler.push(JsonEventHandler::stdout(log_level)),
+        }
+        if let Some(path) = &self.events_file {
+            msg_handler.push(JsonEventHandler::create(path, LogLevel::Verbose)?);
+        }
+        Ok(msg_handler)
+    }
+}
+
+/// Parses a resource limit given as `RESOURCE=N`.
+fn parse_limit(value: &str) -> Result<(String, usize), String> {
+    let (resource, limit) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected RESOURCE=N, got `{}`", value))?;
+    let limit = limit
+        .parse::<usize>()
+        .map_err(|e| format!("invalid limit `{}`: {}", limit, e))?;
+    if limit == 0 {
+        return Err("the limit must be at least 1".to_string());
+    }
+    Ok((resource.to_string(), limit))
+}
 
 /// Describes a way to define a series of steps to be executed and processes each one.
 /// The implementation defines which steps are executed in what order and which can be run in
@@ -33,53 +174,85 @@ pub trait CLIStepExecutor {
     /// ```
     async fn set_properties(&self, props: ExecutorProperties) -> ExecutorProperties;
 
-    /// Sets up the method to notify the caller based on cargo features. Calls the impl
+    /// Sets up the method to notify the caller based on the output options. Calls the impl
     /// `set_properties` and uses that to define the behavior on how to execute all the steps.
     ///
     ///
-    /// Two current supported methods for monitoring are, the default, progress bars and standard
-    /// `println!` macro.
-    async fn execute(&self) -> Result<(), String> {
-        let msg_handler = ProgressBarsEventHandler::new(LogLevel::Info);
-        #[cfg(feature = "println")]
-        let msg_handler = ConsoleLogEventHandler::new(LogLevel::Info);
-        let command_props = self.set_properties(ExecutorProperties::new()).await;
-        StepExecutor::new(msg_handler)
-            .build_steps(command_props)
-            .run()
-            .await?;
-        Ok(())
-    }
-}
-
-/// Simple wrapper around a `tokio::process::Command` that returns a
-/// `Result<String, String>`.
-///
-/// Result will likely get refactored to be more useful.
-pub async fn cmd<F, Fut>(fun: F, cmd_name: &str) -> Result<String, String>
-where
-    F: FnOnce() -> Fut,
-    Fut: Future<Output = Result<std::process::Output, std::io::Error>>,
-{
-    let result = fun().await;
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                return Ok(format!("Completed running `{}`.", cmd_name));
-            } else {
-                return Err(format!(
-                    "Error running `{}`. stderr: {}",
-                    cmd_name,
-                    String::from_utf8(output.stderr).unwrap()
-                ));
+    /// Progress is reported with progress bars, plain lines or json, see `--output`. With
+    /// `--events-file` every event is also written to a file.
+    ///
+    /// Every run is recorded in a [RunJournal](), when a run fails it can be picked up again with
+    /// `--resume <run-id>`.
+    ///
+    /// With `--dry-run` only the plan of the run is printed, and with `--graph` only a diagram of
+    /// the steps. Both fail when the steps are invalid, a dry run also fails when a step would
+    /// error.
+    async fn execute(&self, options: &ExecutorOptions) -> Result<(), OecliError> {
+        if let Some(format) = options.graph {
+            let command_props = self.set_properties(options.properties()).await;
+            // Nothing runs, so no events are reported.
+            let executor =
+                StepExecutor::from_executor_properties(command_props, FanOutEventHandler::new());
+            println!("{}", executor.diagram().await.render(format));
+            executor.validate().await?;
+            return Ok(());
+        }
+        if options.dry_run {
+            let command_props = self.set_properties(options.properties()).await;
+            let mut executor =
+                StepExecutor::from_executor_properties(command_props, FanOutEventHandler::new());
+            if let Some(run_id) = &options.resume {
+                executor = executor.with_journal(RunJournal::resume(run_id).await?);
+            }
+            let plan = executor.plan().await;
+            println!("{}", plan);
+            executor.validate().await?;
+            let (_, _, errors, _) = plan.totals();
+            if errors > 0 {
+                return Err(OecliError::Precondition(format!(
+                    "{} step(s) would error.",
+                    errors
+                )));
             }
+            return Ok(());
         }
-        Err(e) => {
-            return Err(format!(
-                "Failed to run `{}`. Error: {}",
-                cmd_name,
-                e.to_string()
-            ));
+        let command_props = self.set_properties(options.properties()).await;
+        let executor =
+            StepExecutor::from_executor_properties(command_props, options.event_handler()?);
+        // Nothing has run yet, so there is nothing to resume.
+        executor.validate().await?;
+        let journal = match &options.resume {
+            Some(run_id) => RunJournal::resume(run_id).await?,
+            None => RunJournal::new().await?,
+        };
+        let run_id = journal.run_id().to_string();
+        let executor = executor.with_journal(journal);
+        let result = executor.run().await;
+        let timings = executor.timings().await;
+        if options.timings {
+            eprintln!("\n{}", timings);
         }
+        // A failed run is reported before a trace that could not be written.
+        let trace = match &options.trace_file {
+            Some(path) => timings.write_trace(path),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            eprintln!("Resume this run with `--resume {}`.", run_id);
+        }
+        result?;
+        trace
+    }
+}
+
+/// Runs the program with the [ProcessRunner](crate::step::process::ProcessRunner) of the run
+/// context and captures its output, see the runner for how the output is streamed. A process that
+/// exits with a non-zero exit code results in an [OecliError::Exit]().
+pub async fn cmd(ctx: &RunContext, invocation: Invocation) -> Result<CommandOutput, OecliError> {
+    let output = ctx.run(&invocation).await?;
+    if output.success() {
+        Ok(output)
+    } else {
+        Err(OecliError::exit(&output))
     }
 }
@@ -0,0 +1,173 @@
+//! Errors that can occur while processing a command.
+
+use crate::step::process::CommandOutput;
+use crate::step::{FailureReport, StepProcessError};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Exit code used for errors that do not have a more specific exit code.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Every error oecli can run into. Each variant maps to a distinct process exit code so scripts
+/// can tell the causes apart.
+#[derive(Clone, Debug)]
+pub enum OecliError {
+    /// The process could not be started, most likely the program is not installed.
+    Spawn {
+        command: String,
+        source: Arc<std::io::Error>,
+    },
+    /// The process ran but exited with a non-zero exit code. `code` is `None` when the process
+    /// was terminated by a signal.
+    Exit {
+        command: String,
+        code: Option<i32>,
+        stdout: String,
+        stderr: String,
+    },
+    /// Not authenticated with a service the command depends on, such as the GitHub CLI.
+    Auth(String),
+    /// A condition that must hold before a step can run does not.
+    Precondition(String),
+    /// A value a step needs has not been set by an earlier step. Exits with the code of
+    /// `Precondition`.
+    Unset(String),
+    /// Reading or writing a file failed.
+    Io {
+        context: String,
+        source: Arc<std::io::Error>,
+    },
+    /// The dependencies between steps are invalid. A dependency is missing or steps depend on
+    /// each other.
+    InvalidGraph(String),
+    /// A workflow file could not be read into steps. Names the file.
+    InvalidWorkflow { path: String, msg: String },
+    /// The step did not finish within the timeout.
+    Timeout(Duration),
+    /// The run was cancelled, for example by pressing Ctrl-C.
+    Cancelled,
+    /// A step failed, names the step that caused the error.
+    Step(Box<StepProcessError>),
+    /// Several steps failed during the run.
+    Failures(FailureReport),
+    /// A step panicked, which is a bug in oecli. `location` is the source file and line the panic
+    /// happened at, when it is known.
+    Panic {
+        msg: String,
+        location: Option<String>,
+    },
+    /// An unexpected failure within oecli itself.
+    Internal(String),
+}
+
+impl OecliError {
+    pub fn spawn(command: &str, source: std::io::Error) -> OecliError {
+        OecliError::Spawn {
+            command: command.to_string(),
+            source: Arc::new(source),
+        }
+    }
+
+    pub fn io(context: &str, source: std::io::Error) -> OecliError {
+        OecliError::Io {
+            context: context.to_string(),
+            source: Arc::new(source),
+        }
+    }
+
+    /// Builds the error from the output of a process that did not exit successfully.
+    pub fn exit(output: &CommandOutput) -> OecliError {
+        OecliError::Exit {
+            command: output.command.clone(),
+            code: output.code,
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+        }
+    }
+
+    /// The exit code oecli exits with when this error ends the process.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OecliError::Spawn { .. } => 3,
+            OecliError::Exit { .. } => 4,
+            OecliError::Auth(_) => 5,
+            OecliError::Precondition(_) | OecliError::Unset(_) => 6,
+            OecliError::Io { .. } => 7,
+            OecliError::Timeout(_) => 8,
+            OecliError::InvalidGraph(_) => 9,
+            OecliError::InvalidWorkflow { .. } => 10,
+            OecliError::Cancelled => 130,
+            OecliError::Step(step_error) => step_error.error.exit_code(),
+            OecliError::Failures(report) => report
+                .failures
+                .first()
+                .map_or(EXIT_FAILURE, |failure| failure.error.exit_code()),
+            OecliError::Panic { .. } | OecliError::Internal(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl Display for OecliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OecliError::Spawn { command, source } => {
+                write!(f, "Failed to run `{}`. {}", command, source)
+            }
+            OecliError::Exit {
+                command,
+                code,
+                stderr,
+                ..
+            } => {
+                match code {
+                    Some(code) => write!(f, "`{}` exited with code {}.", command, code)?,
+                    None => write!(f, "`{}` was terminated by a signal.", command)?,
+                }
+                if !stderr.trim().is_empty() {
+                    write!(f, "\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            OecliError::Auth(msg) => write!(f, "Authentication failed. {}", msg),
+            OecliError::Precondition(msg) | OecliError::Unset(msg) => write!(f, "{}", msg),
+            OecliError::Io { context, source } => write!(f, "{}\n{}", context, source),
+            OecliError::InvalidGraph(msg) => write!(f, "Invalid step dependencies. {}", msg),
+            OecliError::InvalidWorkflow { path, msg } => {
+                write!(f, "Invalid workflow {}. {}", path, msg)
+            }
+            OecliError::Timeout(timeout) => {
+                write!(f, "Timed out after {}s.", timeout.as_secs_f32())
+            }
+            OecliError::Cancelled => write!(f, "Cancelled."),
+            OecliError::Step(step_error) => write!(f, "{}", step_error),
+            OecliError::Failures(report) => write!(f, "{}", report),
+            OecliError::Panic { msg, location } => {
+                write!(f, "Panicked")?;
+                if let Some(location) = location {
+                    write!(f, " at {}", location)?;
+                }
+                write!(f, ". {}", msg)
+            }
+            OecliError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for OecliError {}
+
+impl From<StepProcessError> for OecliError {
+    fn from(step_error: StepProcessError) -> Self {
+        OecliError::Step(Box::new(step_error))
+    }
+}
+
+/// A single failure is reported as the error of that step.
+impl From<FailureReport> for OecliError {
+    fn from(mut report: FailureReport) -> Self {
+        match report.failures.len() {
+            1 => report.failures.remove(0).into(),
+            _ => OecliError::Failures(report),
+        }
+    }
+}
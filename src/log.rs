@@ -1,7 +1,7 @@
 //! Logging utilities.
 
-/// How much logging to provide to the user
-#[allow(dead_code)]
+/// How much logging to provide to the user. Every level includes the levels before it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum LogLevel {
     /// Do not print any information.
     Silent,
@@ -14,3 +14,28 @@ pub enum LogLevel {
     /// Print all output of all steps and what they do.
     Verbose,
 }
+
+impl LogLevel {
+    const LEVELS: [LogLevel; 5] = [
+        LogLevel::Silent,
+        LogLevel::Error,
+        LogLevel::Warning,
+        LogLevel::Info,
+        LogLevel::Verbose,
+    ];
+
+    /// Whether messages of the given level are printed at this level.
+    pub fn shows(&self, level: LogLevel) -> bool {
+        *self >= level
+    }
+
+    /// Moves `more` levels up and `less` levels down, staying within Silent and Verbose.
+    pub fn adjust(self, more: u8, less: u8) -> LogLevel {
+        let index = LogLevel::LEVELS
+            .iter()
+            .position(|l| *l == self)
+            .unwrap_or(0);
+        let index = (index + more as usize).saturating_sub(less as usize);
+        LogLevel::LEVELS[index.min(LogLevel::LEVELS.len() - 1)]
+    }
+}
@@ -4,12 +4,15 @@
 //! with development within the Overengineered ecosystem.
 
 mod command;
+mod error;
 mod log;
 mod step;
 mod subcommands;
+#[cfg(feature = "testcmd")]
 mod test;
 
 use clap::Parser;
+use command::ExecutorOptions;
 use subcommands::Commands;
 
 /// The main entry point for oecli, uses clap to parse the command line arguments into several
@@ -29,14 +32,16 @@ use subcommands::Commands;
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    #[clap(flatten)]
+    options: ExecutorOptions,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    let result = cli.command.process().await;
+    let result = cli.command.process(&cli.options).await;
     if let Err(err) = result {
-        eprintln!("\x1b[93mError has occurred:\x1b[0m {:#?}", err);
-        std::process::exit(1);
+        eprintln!("\x1b[93mError has occurred:\x1b[0m {}", err);
+        std::process::exit(err.exit_code());
     }
 }
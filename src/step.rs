@@ -2,20 +2,42 @@
 //! Meaning that if a step fails, the process can be restarted and all previously completed steps
 //! will be skipped.
 
+mod condition;
+mod context;
 pub mod event;
 mod executor;
+mod graph;
+mod journal;
 pub mod msg_handler;
+pub mod output;
+pub mod process;
+pub mod resource;
+mod retry;
 mod sequence;
+#[cfg(test)]
+pub mod testing;
 
+use crate::error::OecliError;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fmt::Display,
     marker::{Send, Sync},
+    time::Duration,
 };
+use tokio::time::Instant;
 
-pub use executor::{ExecutorProperties, StepExecutor};
-pub use sequence::{NextSequenceStep, StepSequence};
+pub use condition::Condition;
+pub use context::{ContextKey, RunContext};
+pub use executor::{
+    DiagramFormat, ExecutorProperties, FailureMode, FailureReport, StepExecutor, StepStatus,
+};
+use graph::GraphStep;
+pub use graph::StepGraph;
+pub use journal::RunJournal;
+pub use retry::{Backoff, RetryPolicy};
+pub use sequence::StepSequence;
 
 /// StepItem wrapped in a Box that implements Sync + Send to send the StepItem between threads for
 /// execution and message processing.
@@ -23,85 +45,104 @@ pub type BoxedStepItem = Box<dyn StepItem + Sync + Send>;
 
 /// A Step can be declared as a single step, or a sequence of steps. A sequence is useful to define
 /// on order of dependencies, where multiple individual steps can be completed at once.
+#[allow(clippy::enum_variant_names)]
 pub enum Step {
     /// A single step item.
     Step(BoxedStepItem),
     /// A sequence of many step items.
     Sequence(StepSequence),
+    /// Step items that each start once the steps they depend on have completed.
+    Graph(StepGraph),
+    /// Several steps that run at the same time.
+    #[allow(dead_code)]
     Parallel(ParallelSteps),
 }
 
 impl Step {
-    fn get_id(&self) -> StepId {
-        match self {
-            Step::Step(step) => step.get_id(),
-            Step::Sequence(seq) => seq.get_id(),
-            Step::Parallel(par) => par.get_id(),
-        }
-    }
-
-    /// Too lazy should return struct
-    async fn should_run(&mut self, steps: Steps) -> Vec<(StepId, ShouldRunResult)> {
-        let mut steps = steps;
+    /// Only runs the step when the condition holds, otherwise it is skipped. For a sequence,
+    /// graph or parallel steps, the condition guards every step item within.
+    pub fn run_if(self, condition: Condition) -> Step {
         match self {
-            Step::Step(step) => {
-                let should_run = step.should_run().await;
-                vec![(step.get_id(), should_run)]
-            }
-            Step::Sequence(seq) => {
-                let step_ids = seq.get_next();
-                let mut results = Vec::new();
-                for step_id in step_ids {
-                    let step = steps.take(&step_id);
-                    let should_run = match step {
-                        Some(mut step) => {
-                            let res = step.should_run(steps).await;
-                            steps.set(step);
-                            res
-                        }
-                        None => {
-                            unimplemented!();
-                        }
-                    };
-                    results.extend(should_run);
-                }
-                results
-            }
-            Step::Parallel(par) => {
-                let step_ids = par.steps;
-                let mut results = Vec::new();
-                for step_id in step_ids {
-                    let step = steps.take(&step_id);
-                    let should_run = match step {
-                        Some(step) => {
-                            let res = step.should_run(steps).await;
-                            steps.set(step);
-                            res
-                        }
-                        None => {
-                            unimplemented!();
-                        }
-                    };
-                    results.extend(should_run);
-                }
-                results
+            Step::Step(step_item) => Step::Step(condition.guard(step_item)),
+            Step::Sequence(sequence) => Step::Sequence(sequence.skip_unless(condition)),
+            Step::Graph(mut graph) => {
+                graph.steps = graph
+                    .steps
+                    .into_iter()
+                    .map(|graph_step| GraphStep {
+                        dependencies: graph_step.dependencies,
+                        id: graph_step.id,
+                        step: condition.clone().guard(graph_step.step),
+                    })
+                    .collect();
+                Step::Graph(graph)
             }
+            Step::Parallel(parallel) => Step::Parallel(ParallelSteps::new(
+                parallel
+                    .steps
+                    .into_iter()
+                    .map(|step| step.run_if(condition.clone()))
+                    .collect(),
+            )),
         }
     }
 }
 
+/// A single unit of work that is processed by the [StepExecutor](crate::step::StepExecutor).
 #[async_trait]
 pub trait StepItem {
+    /// Short title of the step, used when informing the user of the progress.
+    fn title(&self) -> String;
+    /// Longer description of what the step will do.
+    fn description(&self) -> String;
     /// An asynchronous function that will be called to determine if this step should be executed.
-    async fn should_run(self: Box<Self>) -> ShouldRunResult;
-    /// The body of work to be completed by this step.
-    async fn execute(self: Box<Self>) -> Result<String, String>;
+    /// Steps that produce values for later steps should also store them in the context when the
+    /// step is skipped.
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult;
+    /// The body of work to be completed by this step. Called again for every retry when the step
+    /// declares a [RetryPolicy].
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError>;
+
+    /// How long the step may take before it is interrupted. Overrides the step timeout of the
+    /// [ExecutorProperties]().
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Reverts the changes made by `execute`. When the run rolls back after a failure, it is
+    /// called in reverse order for every step that completed during the run. Returns `None` when
+    /// the step has nothing to undo.
+    async fn undo(&self, _ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        None
+    }
+
+    /// Names of the [resources](crate::step::resource) the step uses. The executor limits how many
+    /// steps use the same resource at once.
+    fn resources(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// How a failed `execute` is retried. Steps are not retried by default.
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Details of the step passed along to the event handler.
+    fn get_step_details(&self, id: StepId) -> StepDetails {
+        StepDetails {
+            id,
+            title: self.title(),
+            description: self.description(),
+        }
+    }
 }
 
 #[derive(Clone)]
 /// Contains the details of a step. Used to pass information about progress to the message handler.
 pub struct StepDetails {
+    pub id: StepId,
     pub title: String,
+    pub description: String,
 }
 
 pub enum ShouldRunResult {
@@ -109,48 +150,50 @@ pub enum ShouldRunResult {
     Ok,
     /// The step has already completed and skipped.
     Skip,
+    /// The step is skipped because a [Condition] it is guarded by does not hold. Describes the
+    /// condition.
+    Unmet(String),
     /// A hard error that should interrupt and exit.
-    Error(StepProcessError),
+    Error(OecliError),
 }
 
+/// An error that occurred while processing a step. Names the step the error belongs to.
+#[derive(Clone, Debug)]
 pub struct StepProcessError {
-    pub message: String,
+    pub title: String,
+    pub error: OecliError,
 }
 
 impl StepProcessError {
-    pub fn new(message: &str) -> StepProcessError {
+    pub fn new(title: &str, error: OecliError) -> StepProcessError {
         StepProcessError {
-            message: message.to_string(),
+            title: title.to_string(),
+            error,
         }
     }
 }
 
 impl Display for StepProcessError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}: {}", self.title, self.error)
     }
 }
 
 /// A collection of steps to run in parallel.
 pub struct ParallelSteps {
-    step_id: StepId,
-    pub steps: Vec<StepId>,
+    pub steps: Vec<Step>,
 }
 
 impl ParallelSteps {
-    pub fn new(steps: Vec<StepId>) -> ParallelSteps {
-        ParallelSteps {
-            step_id: StepId::new_random(),
-            steps,
-        }
-    }
-
-    pub fn get_id(&self) -> StepId {
-        self.step_id
+    #[allow(dead_code)]
+    pub fn new(steps: Vec<Step>) -> ParallelSteps {
+        ParallelSteps { steps }
     }
 }
 
-#[derive(Clone, Eq, Hash)]
+/// Identifies a single [StepItem]() once it has been registered with the executor.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
 pub struct StepId {
     pub id: uuid::Uuid,
 }
@@ -162,20 +205,26 @@ impl StepId {
             id: uuid::Uuid::new_v4(),
         }
     }
-}
 
-impl PartialEq for StepId {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+    /// Generates an id using UUID v5 from the key. The same key always results in the same id,
+    /// which allows a step to be recognized between runs.
+    pub fn from_key(key: &str) -> StepId {
+        StepId {
+            id: uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, key.as_bytes()),
+        }
     }
 }
 
-pub trait StepWithId {
-    fn get_id(&self) -> StepId;
+impl Display for StepId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
 }
 
+/// Registry of every step known to the executor, keyed by [StepId]. The step item itself is taken
+/// out of the registry once it is executed, the details and status remain.
 pub struct Steps {
-    inner: HashMap<StepId, Step>,
+    inner: HashMap<StepId, StepState>,
 }
 
 impl Steps {
@@ -185,17 +234,60 @@ impl Steps {
         }
     }
 
-    pub fn add(&mut self, step: Step) {
-        let id = step.get_id();
-        self.inner.insert(id, step);
+    /// Registers the step item and returns the id it can be looked up with. The key is the
+    /// position of the step within the step graph, combined with the title it makes up the id.
+    pub fn add(&mut self, key: &str, step: BoxedStepItem) -> StepId {
+        let id = StepId::from_key(&format!("{}:{}", key, step.title()));
+        self.insert(id, step);
+        id
+    }
+
+    /// Registers the step item under an id that was assigned up front.
+    pub fn insert(&mut self, id: StepId, step: BoxedStepItem) {
+        self.inner.insert(id, StepState::new(id, step));
+    }
+
+    pub fn get(&self, id: &StepId) -> Option<&StepState> {
+        self.inner.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &StepId) -> Option<&mut StepState> {
+        self.inner.get_mut(id)
+    }
+}
+
+/// Wraps a step to be executed, and acts as a small state machine tracking its [StepStatus].
+pub struct StepState {
+    step: Option<BoxedStepItem>,
+    pub details: StepDetails,
+    pub status: StepStatus,
+    /// Timeout declared by the step itself.
+    pub timeout: Option<Duration>,
+    /// When the step started, once it had the resources it uses.
+    pub started: Option<Instant>,
+    /// When the step reached a finished status, not counting a rollback.
+    pub finished: Option<Instant>,
+}
+
+impl StepState {
+    fn new(id: StepId, step: BoxedStepItem) -> StepState {
+        StepState {
+            details: step.get_step_details(id),
+            status: StepStatus::NotStarted,
+            timeout: step.timeout(),
+            started: None,
+            finished: None,
+            step: Some(step),
+        }
     }
 
-    pub fn set(&mut self, step: Step) {
-        let id = step.get_id();
-        self.inner.insert(id, step);
+    /// The step item, if it has not been taken out for execution.
+    pub fn step(&self) -> Option<&BoxedStepItem> {
+        self.step.as_ref()
     }
 
-    pub fn take(&self, id: &StepId) -> Option<Step> {
-        self.inner.remove(id)
+    /// Takes the step item out of the state so it can be executed.
+    pub fn take(&mut self) -> Option<BoxedStepItem> {
+        self.step.take()
     }
 }
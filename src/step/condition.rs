@@ -0,0 +1,254 @@
+//! Conditions that decide whether a step runs, without writing a new [StepItem] for every
+//! condition.
+//!
+//! A condition is checked right before the step would run, so it sees the values earlier steps
+//! stored in the [RunContext]. Values such as CLI arguments are captured by the closure.
+
+use crate::error::OecliError;
+use crate::step::process::Invocation;
+use crate::step::{
+    BoxedStepItem, ContextKey, RetryPolicy, RunContext, ShouldRunResult, StepDetails, StepId,
+    StepItem,
+};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+type Predicate =
+    dyn Fn(RunContext) -> BoxFuture<'static, Result<bool, OecliError>> + Send + Sync + 'static;
+
+/// An asynchronous predicate a step is guarded by. Cloning the condition shares the predicate.
+///
+/// Example:
+///
+/// ```
+/// let force = self.force;
+/// let condition = Condition::new("--force is set", move |_ctx| async move { Ok(force) });
+/// ```
+#[derive(Clone)]
+pub struct Condition {
+    description: String,
+    predicate: Arc<Predicate>,
+}
+
+impl Condition {
+    /// The description completes the sentence "Only runs if …".
+    pub fn new<F, Fut>(description: &str, predicate: F) -> Condition
+    where
+        F: Fn(RunContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<bool, OecliError>> + Send + 'static,
+    {
+        Condition {
+            description: description.to_string(),
+            predicate: Arc::new(move |ctx| Box::pin(predicate(ctx))),
+        }
+    }
+
+    /// Holds when the file or directory exists once the step is reached.
+    pub fn path_exists(path: &str) -> Condition {
+        let path = path.to_string();
+        Condition::new(&format!("{} exists", path), move |_ctx| {
+            let exists = Path::new(&path).exists();
+            async move { Ok(exists) }
+        })
+    }
+
+    /// Holds when the environment variable is set to a non empty value.
+    pub fn env_set(name: &str) -> Condition {
+        let name = name.to_string();
+        Condition::new(&format!("{} is set", name), move |_ctx| {
+            let set = std::env::var_os(&name).is_some_and(|value| !value.is_empty());
+            async move { Ok(set) }
+        })
+    }
+
+    /// Holds when an earlier step stored a value under the key.
+    #[allow(dead_code)]
+    pub fn context_set<T: 'static>(key: &ContextKey<T>) -> Condition {
+        let name = key.name().to_string();
+        Condition::new(&format!("{} is known", name), move |ctx| {
+            let known = ctx.contains(&name);
+            async move { Ok(known) }
+        })
+    }
+
+    /// Holds when git reports changes to the file, compared to the last commit of the repository
+    /// the file belongs to. Untracked files count as changed.
+    #[allow(dead_code)]
+    pub fn changed(path: &str) -> Condition {
+        let path = path.to_string();
+        Condition::new(&format!("{} changed", path), move |ctx| {
+            let file = Path::new(&path);
+            let dir = file
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            let name = file.file_name().unwrap_or_default();
+            let status = Invocation::new("git")
+                .args(&["status", "--porcelain", "--"])
+                .arg(&name.to_string_lossy())
+                .current_dir(&dir.to_string_lossy());
+            let path = path.clone();
+            async move {
+                let output = ctx.run(&status).await?;
+                if !output.success() {
+                    return Err(OecliError::Precondition(format!(
+                        "Unable to check if {} changed. {}",
+                        path,
+                        output.stderr.trim()
+                    )));
+                }
+                Ok(!output.stdout.is_empty())
+            }
+        })
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub async fn check(&self, ctx: &RunContext) -> Result<bool, OecliError> {
+        (self.predicate)(ctx.clone()).await
+    }
+
+    /// Wraps the step so it only runs when the condition holds.
+    pub fn guard(self, step: BoxedStepItem) -> BoxedStepItem {
+        Box::new(Guarded {
+            condition: self,
+            step,
+        })
+    }
+}
+
+/// A step that only runs when the condition holds. Otherwise it is skipped, like a step that has
+/// already completed. Everything else is left to the wrapped step.
+struct Guarded {
+    condition: Condition,
+    step: BoxedStepItem,
+}
+
+#[async_trait]
+impl StepItem for Guarded {
+    fn title(&self) -> String {
+        self.step.title()
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "{} Only runs if {}.",
+            self.step.description(),
+            self.condition.description()
+        )
+    }
+
+    /// Checks the condition before asking the step itself.
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        match self.condition.check(ctx).await {
+            Ok(true) => self.step.should_run(ctx).await,
+            Ok(false) => ShouldRunResult::Unmet(self.condition.description().to_string()),
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        self.step.execute(ctx).await
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.step.timeout()
+    }
+
+    async fn undo(&self, ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        self.step.undo(ctx).await
+    }
+
+    fn resources(&self) -> Vec<String> {
+        self.step.resources()
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.step.retry_policy()
+    }
+
+    fn get_step_details(&self, id: StepId) -> StepDetails {
+        StepDetails {
+            description: self.description(),
+            ..self.step.get_step_details(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::{FakeOutput, FakeProcessRunner, TempDir};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn path_exists_holds_once_the_path_exists() {
+        let dir = TempDir::new();
+        let path = format!("{}/package.json", dir.path());
+        let condition = Condition::path_exists(&path);
+        let ctx = RunContext::default();
+
+        assert!(!condition.check(&ctx).await.unwrap());
+        dir.write("package.json", "{}");
+        assert!(condition.check(&ctx).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn changed_asks_git_within_the_directory_of_the_file() {
+        let runner = FakeProcessRunner::new().expect(
+            &["git", "status", "--porcelain", "--", "hosts.yml"],
+            FakeOutput::success(" M hosts.yml\n"),
+        );
+
+        let changed = Condition::changed("inventory/hosts.yml")
+            .check(&runner.context())
+            .await
+            .unwrap();
+
+        assert!(changed);
+        assert_eq!(
+            runner.calls()[0].current_dir,
+            Some(PathBuf::from("inventory"))
+        );
+    }
+
+    #[tokio::test]
+    async fn changed_does_not_hold_for_a_clean_file() {
+        let runner = FakeProcessRunner::new().expect(
+            &["git", "status", "--porcelain", "--", "hosts.yml"],
+            FakeOutput::success(""),
+        );
+
+        let changed = Condition::changed("hosts.yml")
+            .check(&runner.context())
+            .await
+            .unwrap();
+
+        assert!(!changed);
+        assert_eq!(runner.calls()[0].current_dir, Some(PathBuf::from(".")));
+    }
+
+    #[tokio::test]
+    async fn changed_fails_outside_a_repository() {
+        let runner = FakeProcessRunner::new().expect(
+            &["git", "status", "--porcelain", "--", "hosts.yml"],
+            FakeOutput::failure(128, "fatal: not a git repository"),
+        );
+
+        let error = Condition::changed("hosts.yml")
+            .check(&runner.context())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Unable to check if hosts.yml changed. fatal: not a git repository"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Values produced by one step and consumed by later steps, such as the GitHub owner or the path
+//! a repository was cloned to.
+//!
+//! Every value is stored under a typed [ContextKey]. Values are kept as json so the run journal
+//! can persist them, a resumed run starts with the values of the run it resumes.
+//!
+//! The context also carries the [ProcessRunner] every step starts its programs with.
+
+use crate::error::OecliError;
+use crate::step::process::{CommandOutput, Invocation, ProcessRunner, SystemProcessRunner};
+use regex::{Captures, Regex};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+/// Names a value in the [RunContext] and the type it is stored as.
+pub struct ContextKey<T> {
+    name: Cow<'static, str>,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T> ContextKey<T> {
+    pub const fn new(name: &'static str) -> ContextKey<T> {
+        ContextKey {
+            name: Cow::Borrowed(name),
+            value_type: PhantomData,
+        }
+    }
+
+    /// A key whose name is only known at runtime, such as a name read from a workflow file.
+    pub fn named(name: &str) -> ContextKey<T> {
+        ContextKey {
+            name: Cow::Owned(name.to_string()),
+            value_type: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Shared by every step of a run. Cloning the context shares the same values.
+#[derive(Clone)]
+pub struct RunContext {
+    values: Arc<RwLock<BTreeMap<String, serde_json::Value>>>,
+    runner: Arc<dyn ProcessRunner>,
+}
+
+impl Default for RunContext {
+    fn default() -> Self {
+        RunContext {
+            values: Arc::default(),
+            runner: Arc::new(SystemProcessRunner),
+        }
+    }
+}
+
+impl RunContext {
+    /// Replaces the values with the values persisted by a previous run. Keeps the process runner.
+    pub fn with_values(self, values: BTreeMap<String, serde_json::Value>) -> RunContext {
+        RunContext {
+            values: Arc::new(RwLock::new(values)),
+            ..self
+        }
+    }
+
+    /// Starts every program of the run with the runner, rather than as a child process.
+    pub fn with_runner(self, runner: Arc<dyn ProcessRunner>) -> RunContext {
+        RunContext { runner, ..self }
+    }
+
+    /// Runs the program with the process runner of the run. The exit code is part of the output,
+    /// see [cmd](crate::command::cmd) to fail on a non-zero exit code.
+    pub async fn run(&self, invocation: &Invocation) -> Result<CommandOutput, OecliError> {
+        self.runner.run(invocation).await
+    }
+
+    /// Stores the value, replacing any value stored under the same key.
+    pub fn set<T: Serialize>(&self, key: &ContextKey<T>, value: T) -> Result<(), OecliError> {
+        let value = serde_json::to_value(value).map_err(|e| {
+            OecliError::Internal(format!(
+                "Unable to store {} in the context. {}",
+                key.name, e
+            ))
+        })?;
+        self.write().insert(key.name().to_string(), value);
+        Ok(())
+    }
+
+    /// The value stored under the key. `None` when no step has set it.
+    pub fn get<T: DeserializeOwned>(&self, key: &ContextKey<T>) -> Result<Option<T>, OecliError> {
+        let value = match self.read().get(key.name()) {
+            Some(value) => value.clone(),
+            None => return Ok(None),
+        };
+        serde_json::from_value(value).map(Some).map_err(|e| {
+            OecliError::Internal(format!("Value of {} has the wrong type. {}", key.name, e))
+        })
+    }
+
+    /// The value stored under the key, an error when no step has set it.
+    pub fn require<T: DeserializeOwned>(&self, key: &ContextKey<T>) -> Result<T, OecliError> {
+        self.get(key)?.ok_or_else(|| {
+            OecliError::Unset(format!("{} has not been set by an earlier step.", key.name))
+        })
+    }
+
+    /// Whether a value is stored under the name, whatever its type.
+    pub fn contains(&self, name: &str) -> bool {
+        self.read().contains_key(name)
+    }
+
+    /// Replaces every `{{name}}` in the template with the value stored under the name. Strings are
+    /// inserted as they are, other values as json. Fails when a name has no value.
+    pub fn render(&self, template: &str) -> Result<String, OecliError> {
+        let re = Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").unwrap();
+        let values = self.read();
+        let mut missing = None;
+        let rendered = re.replace_all(template, |cap: &Captures| match values.get(&cap[1]) {
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => {
+                missing.get_or_insert_with(|| cap[1].to_string());
+                String::new()
+            }
+        });
+        match missing {
+            Some(name) => Err(OecliError::Unset(format!(
+                "`{}` refers to {}, which has not been set by an earlier step.",
+                template, name
+            ))),
+            None => Ok(rendered.into_owned()),
+        }
+    }
+
+    /// A copy of every value, used to persist the context.
+    pub fn values(&self) -> BTreeMap<String, serde_json::Value> {
+        self.read().clone()
+    }
+
+    // A panic while holding the lock can not leave a half written value behind, so a poisoned
+    // lock is still safe to use.
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, serde_json::Value>> {
+        self.values.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, serde_json::Value>> {
+        self.values.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -2,6 +2,10 @@
 //! processed is considered an event, as well as end, starting a new sequence, or ending one. As
 //! well as skipped steps.
 
+use crate::error::OecliError;
+use crate::step::StepStatus;
+use std::time::Duration;
+
 /// During the lifecycle of a step, the following events can be emitted:
 pub enum StepEvent<'a> {
     End(&'a EventData),
@@ -10,6 +14,17 @@ pub enum StepEvent<'a> {
     NewSequence(NewSequenceEvent),
     EndSequence(&'a EventData),
     Skip(&'a EventData),
+    /// The step timed out or the run was cancelled while it was in progress.
+    Interrupt(&'a EventData),
+    /// An attempt to execute the step failed and it will be executed again.
+    Retry(&'a RetryEvent),
+    /// The step was undone while rolling back the run. When undoing failed, the cause is the
+    /// `error` of the event data.
+    Rollback(&'a EventData),
+    /// A line of output written by a process the step started.
+    Output(&'a EventData),
+    /// The step has moved to a new [StepStatus] that is not covered by the other events.
+    Status(StepStatus, &'a EventData),
 }
 
 /// When the Executor publishes an event, that event can provide additional information and
@@ -17,6 +32,8 @@ pub enum StepEvent<'a> {
 pub struct EventData {
     pub msg: String,
     pub sequence_name: String,
+    /// The cause of an [StepEvent::Error].
+    pub error: Option<OecliError>,
 }
 
 impl EventData {
@@ -24,6 +41,52 @@ impl EventData {
         EventData {
             msg: msg.to_string(),
             sequence_name: sequence_name.to_string(),
+            error: None,
+        }
+    }
+
+    /// Event data for a step that failed, the message is the rendered error.
+    pub fn from_error(error: &OecliError, sequence_name: &str) -> EventData {
+        EventData {
+            msg: error.to_string(),
+            sequence_name: sequence_name.to_string(),
+            error: Some(error.clone()),
+        }
+    }
+}
+
+/// Published before a failed step is executed again.
+#[allow(dead_code)]
+pub struct RetryEvent {
+    /// The attempt that failed, starting at 1.
+    pub attempt: usize,
+    pub max_attempts: usize,
+    /// How long the executor waits before the next attempt.
+    pub delay: Duration,
+    /// Describes the retry, the error of the failed attempt is the `error` of the data.
+    pub data: EventData,
+}
+
+impl RetryEvent {
+    pub fn new(
+        attempt: usize,
+        max_attempts: usize,
+        delay: Duration,
+        error: &OecliError,
+        sequence_name: &str,
+    ) -> RetryEvent {
+        let mut data = EventData::from_error(error, sequence_name);
+        data.msg = format!(
+            "Attempt {} of {} failed, retrying in {}s.",
+            attempt,
+            max_attempts,
+            delay.as_secs_f32()
+        );
+        RetryEvent {
+            attempt,
+            max_attempts,
+            delay,
+            data,
         }
     }
 }
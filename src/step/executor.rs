@@ -1,351 +1,1021 @@
 //! The Step Executor is responsible for processing [Steps](crate::step::Step) into
 //! [StepItems](crate::step::StepItem) and executing them.
-
+//!
+//! Every StepItem is registered with the executor and wrapped in a small state machine. As the
+//! step moves through its lifecycle the status is updated and the event handler is notified.
+//!
+//! ```text
+//! NotStarted -> ShouldRun -> Running -> Completed
+//!            |                       -> Error
+//!            -> Skipped
+//!            -> Error
+//! ```
+//!
+//! A step that does not finish before its timeout moves to `TimedOut`, and steps in progress when
+//! the run is cancelled move to `Cancelled`. Steps with a [RetryPolicy](crate::step::RetryPolicy)
+//! stay `Running` while failed attempts are retried.
+//!
+//! When a step fails, the [FailureMode] of its sequence decides what happens to the steps running
+//! alongside it. Every failure is collected into a [FailureReport], steps interrupted because of
+//! another failure are left out of it. A step that panics fails with an [OecliError::Panic]
+//! rather than ending the run.
+//!
+//! When the run fails and rolling back is enabled, the steps that completed during the run are
+//! undone in reverse order and move to `RolledBack`.
+//!
+//! When a [RunJournal] is provided, every finished step is recorded in it. Steps the journal
+//! already lists as completed or skipped are not processed again. A step skipped because its
+//! condition was unmet is left out of the journal, so the condition is checked again on resume.
+
+mod diagram;
+mod failure;
+mod graph;
+mod limits;
+mod panic;
+mod plan;
 mod properties;
+#[cfg(test)]
+mod tests;
+mod timings;
 
-use crate::step::event::{EventData, NewSequenceEvent, StepEvent};
+use crate::error::OecliError;
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent, StepEvent};
 use crate::step::msg_handler::EventHandler;
-use crate::step::{BoxedStepItem, ShouldRunResult, Step};
-use futures::future::join_all;
-use std::collections::VecDeque;
+use crate::step::process::ProcessRunner;
+use crate::step::{
+    output, BoxedStepItem, RunContext, RunJournal, ShouldRunResult, Step, StepDetails, StepId,
+    StepProcessError, Steps,
+};
+use failure::Halt;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use graph::GraphNode;
+use limits::Limits;
+use panic::catch_panic;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::future::{pending, Future};
 use std::marker::{Send, Sync};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, sleep_until, Instant};
+
+pub use diagram::{Diagram, DiagramFormat};
+pub use failure::{FailureMode, FailureReport};
+pub use plan::Plan;
 pub use properties::ExecutorProperties;
+pub use timings::Timings;
 
-/// Processor for taking a set of steps and executing them in order as described by the Executor Properties.
-pub struct StepExecutor<TMH: EventHandler> {
+/// Name of the sequence that top level steps of the [ExecutorProperties] belong to.
+const MAIN_SEQUENCE: &str = "main";
+
+/// The status of a single [StepItem](crate::step::StepItem) within the executor.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum StepStatus {
+    /// The step has been registered but not yet processed.
+    NotStarted,
+    /// `should_run` has determined that the step needs to be executed.
+    ShouldRun,
+    /// The body of the step is being executed.
+    Running,
+    /// `should_run` has determined the step has already been completed.
+    Skipped,
+    /// The step failed either while checking if it should run or while executing.
+    Error,
+    /// The step was executed successfully.
+    Completed,
+    /// The step did not finish before its timeout.
+    TimedOut,
+    /// The run was cancelled while the step was in progress.
+    Cancelled,
+    /// The step completed, but was undone when the run rolled back.
+    RolledBack,
+}
+
+impl StepStatus {
+    /// The step will not change status again.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            StepStatus::Skipped
+                | StepStatus::Error
+                | StepStatus::Completed
+                | StepStatus::TimedOut
+                | StepStatus::Cancelled
+                | StepStatus::RolledBack
+        )
+    }
+}
+
+impl Display for StepStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepStatus::NotStarted => write!(f, "Not Started"),
+            StepStatus::ShouldRun => write!(f, "Should Run"),
+            StepStatus::Running => write!(f, "Running"),
+            StepStatus::Skipped => write!(f, "Skipped"),
+            StepStatus::Error => write!(f, "Error"),
+            StepStatus::Completed => write!(f, "Completed"),
+            StepStatus::TimedOut => write!(f, "Timed Out"),
+            StepStatus::Cancelled => write!(f, "Cancelled"),
+            StepStatus::RolledBack => write!(f, "Rolled Back"),
+        }
+    }
+}
+
+/// The shape of the step graph once every StepItem has been registered with the executor.
+#[derive(Clone)]
+enum StepNode {
+    Item(StepId),
+    Sequence {
+        title: String,
+        batches: Vec<Vec<StepNode>>,
+        failure_mode: Option<FailureMode>,
+    },
+    Graph {
+        title: String,
+        nodes: Vec<GraphNode>,
+        failure_mode: Option<FailureMode>,
+    },
+    Parallel(Vec<StepNode>),
+}
+
+impl StepNode {
+    /// Registers all StepItems within the step and returns the matching node. The key describes
+    /// the position of the step within the graph.
+    fn build(step: Step, key: &str, steps: &mut Steps) -> StepNode {
+        match step {
+            Step::Step(step_item) => StepNode::Item(steps.add(key, step_item)),
+            Step::Sequence(sequence) => {
+                // The conditions are applied once every step has been added to the sequence.
+                let conditions = sequence.conditions;
+                let batches = sequence
+                    .steps
+                    .into_iter()
+                    .map(|batch| {
+                        batch
+                            .into_iter()
+                            .map(|step| conditions.iter().cloned().fold(step, Step::run_if))
+                            .collect()
+                    })
+                    .collect();
+                StepNode::Sequence {
+                    batches: StepNode::build_batches(
+                        batches,
+                        &format!("{}/{}", key, sequence.title),
+                        steps,
+                    ),
+                    title: sequence.title,
+                    failure_mode: sequence.failure_mode,
+                }
+            }
+            Step::Graph(graph) => {
+                // Steps are registered by their position in the graph, the ids handed out by the
+                // graph only serve to declare the dependencies. A step can only depend on steps
+                // that were added before it.
+                let key = format!("{}/{}", key, graph.title);
+                let mut ids = HashMap::new();
+                let nodes = graph
+                    .steps
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, graph_step)| {
+                        let id = steps.add(&format!("{}#{}", key, i), graph_step.step);
+                        ids.insert(graph_step.id, id);
+                        let dependencies = graph_step
+                            .dependencies
+                            .iter()
+                            .map(|dependency| *ids.get(dependency).unwrap_or(dependency))
+                            .collect();
+                        (id, dependencies)
+                    })
+                    .collect();
+                StepNode::Graph {
+                    nodes,
+                    title: graph.title,
+                    failure_mode: graph.failure_mode,
+                }
+            }
+            Step::Parallel(parallel) => StepNode::Parallel(
+                parallel
+                    .steps
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, step)| StepNode::build(step, &format!("{}|{}", key, i), steps))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn build_batches(batches: Vec<Vec<Step>>, key: &str, steps: &mut Steps) -> Vec<Vec<StepNode>> {
+        batches
+            .into_iter()
+            .enumerate()
+            .map(|(i, batch)| {
+                batch
+                    .into_iter()
+                    .enumerate()
+                    .map(|(j, step)| StepNode::build(step, &format!("{}/{}.{}", key, i, j), steps))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Number of StepItems that report their progress to the sequence this node belongs to.
+    /// Nested sequences track their own progress.
+    fn num_steps(&self) -> usize {
+        match self {
+            StepNode::Item(_) => 1,
+            StepNode::Sequence { .. } | StepNode::Graph { .. } => 0,
+            StepNode::Parallel(nodes) => nodes.iter().map(StepNode::num_steps).sum(),
+        }
+    }
+
+    /// Collects the ids of every StepItem in declaration order.
+    fn collect_ids(&self, ids: &mut Vec<StepId>) {
+        match self {
+            StepNode::Item(id) => ids.push(*id),
+            StepNode::Sequence { batches, .. } => {
+                for node in batches.iter().flatten() {
+                    node.collect_ids(ids);
+                }
+            }
+            StepNode::Graph { nodes, .. } => ids.extend(nodes.iter().map(|(id, _)| *id)),
+            StepNode::Parallel(nodes) => {
+                for node in nodes {
+                    node.collect_ids(ids);
+                }
+            }
+        }
+    }
+
+    /// Checks the dependencies of every graph within the node.
+    fn validate(&self, steps: &Steps) -> Result<(), StepProcessError> {
+        match self {
+            StepNode::Item(_) => Ok(()),
+            StepNode::Sequence { batches, .. } => batches
+                .iter()
+                .flatten()
+                .try_for_each(|node| node.validate(steps)),
+            StepNode::Graph { title, nodes, .. } => graph::topological_order(nodes, steps)
+                .map(|_| ())
+                .map_err(|msg| StepProcessError::new(title, OecliError::InvalidGraph(msg))),
+            StepNode::Parallel(nodes) => nodes.iter().try_for_each(|node| node.validate(steps)),
+        }
+    }
+}
+
+/// A step that completed during the run, kept around so it can be undone.
+struct CompletedStep {
+    details: StepDetails,
+    sequence_name: String,
+    step_item: BoxedStepItem,
+}
+
+/// Shared state handed to every spawned task.
+struct ExecutorContext<TMH: EventHandler> {
+    /// Set to true once the run is cancelled.
+    cancel: watch::Receiver<bool>,
+    /// Steps in the order they completed. Only kept when rolling back on failure.
+    completed: Option<Arc<Mutex<Vec<CompletedStep>>>>,
     event_handler: Arc<Mutex<TMH>>,
-    pub steps: Vec<Vec<Step>>,
+    /// Failure mode of the sequence being processed.
+    failure_mode: FailureMode,
+    /// Signals of the batches the node being processed is nested in.
+    halt: Halt,
+    journal: Option<Arc<Mutex<RunJournal>>>,
+    limits: Limits,
+    /// Values the steps pass on to each other.
+    run_context: RunContext,
+    /// Timeout for steps that do not declare their own.
+    step_timeout: Option<Duration>,
+    steps: Arc<Mutex<Steps>>,
+}
+
+impl<TMH: EventHandler> ExecutorContext<TMH> {
+    fn is_cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+}
+
+impl<TMH: EventHandler> Clone for ExecutorContext<TMH> {
+    fn clone(&self) -> Self {
+        ExecutorContext {
+            cancel: self.cancel.clone(),
+            completed: self.completed.clone(),
+            event_handler: self.event_handler.clone(),
+            failure_mode: self.failure_mode,
+            halt: self.halt.clone(),
+            journal: self.journal.clone(),
+            limits: self.limits.clone(),
+            run_context: self.run_context.clone(),
+            step_timeout: self.step_timeout,
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+/// Processor for taking a set of steps and executing them in order as described by the Executor
+/// Properties.
+pub struct StepExecutor<TMH: EventHandler> {
+    cancel: watch::Sender<bool>,
+    context: ExecutorContext<TMH>,
+    root: StepNode,
 }
 
 impl<TMH: EventHandler + Sync + Send + 'static> StepExecutor<TMH> {
-    pub fn new(event_handler: TMH) -> StepExecutor<TMH> {
+    /// From [ExecutorProperties]() sets up the steps to be executed.
+    pub fn from_executor_properties(
+        properties: ExecutorProperties,
+        event_handler: TMH,
+    ) -> StepExecutor<TMH> {
+        let mut steps = Steps::new();
+        let step_timeout = properties.get_step_timeout();
+        let completed = properties
+            .get_rollback_on_failure()
+            .then(|| Arc::new(Mutex::new(Vec::new())));
+        let failure_mode = properties.get_failure_mode();
+        let limits = Limits::new(properties.get_jobs(), properties.get_resource_limits());
+        let root = StepNode::Sequence {
+            title: MAIN_SEQUENCE.to_string(),
+            batches: StepNode::build_batches(properties.get_steps(), MAIN_SEQUENCE, &mut steps),
+            failure_mode: None,
+        };
+        let (cancel, cancel_receiver) = watch::channel(false);
         StepExecutor {
-            event_handler: Arc::new(Mutex::new(event_handler)),
-            steps: Vec::new(),
+            cancel,
+            context: ExecutorContext {
+                cancel: cancel_receiver,
+                completed,
+                event_handler: Arc::new(Mutex::new(event_handler)),
+                failure_mode,
+                halt: Halt::default(),
+                journal: None,
+                limits,
+                run_context: RunContext::default(),
+                step_timeout,
+                steps: Arc::new(Mutex::new(steps)),
+            },
+            root,
         }
     }
 
-    /// From [ExecutorProperties]() sets up the steps to be executed.
-    pub fn build_steps(mut self, properties: ExecutorProperties) -> StepExecutor<TMH> {
-        self.steps = properties.get_steps();
+    /// Records the outcome of every step in the journal. Steps the journal lists as completed or
+    /// skipped by a previous run will be skipped, the values they stored in the [RunContext] are
+    /// restored. Steps whose condition was unmet are checked again.
+    pub fn with_journal(mut self, journal: RunJournal) -> StepExecutor<TMH> {
+        self.context.run_context = self
+            .context
+            .run_context
+            .clone()
+            .with_values(journal.context().clone());
+        self.context.journal = Some(Arc::new(Mutex::new(journal)));
         self
     }
 
-    fn count_steps(self) -> Vec<Vec<Step>> {
-        self.steps
+    /// Starts the programs of every step with the runner, for example a fake in tests.
+    #[allow(dead_code)]
+    pub fn with_process_runner(mut self, runner: Arc<dyn ProcessRunner>) -> StepExecutor<TMH> {
+        self.context.run_context = self.context.run_context.clone().with_runner(runner);
+        self
+    }
+
+    /// Ids of every registered [StepItem]() in the order they were declared.
+    #[allow(dead_code)]
+    pub fn step_ids(&self) -> Vec<StepId> {
+        let mut ids = Vec::new();
+        self.root.collect_ids(&mut ids);
+        ids
+    }
+
+    /// The current status of the step, `None` if the id is not known to this executor.
+    #[allow(dead_code)]
+    pub async fn status(&self, id: &StepId) -> Option<StepStatus> {
+        let steps = self.context.steps.lock().await;
+        steps.get(id).map(|state| state.status)
     }
 
-    /// Main processor function. Will push the [StepItems]() into a queue and process each step in the
-    /// queue. As [StepSequences]() are come across, will add the next StepItem to the queue.
+    /// Checks that the dependencies of every [StepGraph](crate::step::StepGraph) are part of the
+    /// graph and that no steps depend on each other.
+    pub async fn validate(&self) -> Result<(), StepProcessError> {
+        let steps = self.context.steps.lock().await;
+        self.root.validate(&steps)
+    }
+
+    /// Builds the [Plan] of what a run would do, calling `should_run` on every step but never
+    /// executing it.
+    pub async fn plan(&self) -> Plan {
+        let steps = self.context.steps.lock().await;
+        match &self.context.journal {
+            Some(journal) => {
+                let journal = journal.lock().await;
+                Plan::build(
+                    &self.root,
+                    &steps,
+                    Some(&journal),
+                    &self.context.run_context,
+                )
+                .await
+            }
+            None => Plan::build(&self.root, &steps, None, &self.context.run_context).await,
+        }
+    }
+
+    /// The structure of the steps, to be rendered as a diagram.
+    pub async fn diagram(&self) -> Diagram {
+        let steps = self.context.steps.lock().await;
+        Diagram::build(&self.root, &steps)
+    }
+
+    /// How long every step and sequence took, once the run is over.
+    pub async fn timings(&self) -> Timings {
+        let steps = self.context.steps.lock().await;
+        Timings::build(&self.root, &steps)
+    }
+
+    /// Cancels the run. Steps that are in progress are interrupted and no further steps are
+    /// started.
+    pub fn cancel(&self) {
+        let _ = self.cancel.send(true);
+    }
+
+    /// Main processor function. Walks the step graph, each sequence processes its batches in
+    /// order and every step within a batch is spawned as its own tokio task. The dependencies of
+    /// every graph are validated before any step runs.
     ///
-    /// Steps will be attempted to be executed asynchronously, either on one thread or many.
-    pub async fn run(self) -> Result<(), String> {
-        let all_steps = self.steps;
-        // Publish the event for the main sequence.
-        // Should move into own function
-        {
-            let num_steps = all_steps.iter().fold(0, |acc, step| acc + step.len());
-            let mut event_handler = self.event_handler.lock().await;
-            event_handler.handle_event(
-                None,
-                StepEvent::NewSequence(NewSequenceEvent::new(num_steps, "main")),
-            );
+    /// A sequence stops at the first batch that contains an error, what happens to the rest of
+    /// that batch depends on the [FailureMode]. Every failure is returned in the report. When
+    /// rolling back on failure, the completed steps are then undone unless the run was cancelled.
+    /// Pressing Ctrl-C cancels the run.
+    pub async fn run(&self) -> Result<(), FailureReport> {
+        self.validate()
+            .await
+            .map_err(|e| FailureReport { failures: vec![e] })?;
+        let result = self
+            .cancel_on_ctrl_c(process_node(
+                self.root.clone(),
+                MAIN_SEQUENCE.to_string(),
+                self.context.clone(),
+            ))
+            .await
+            .map_err(|failures| FailureReport { failures });
+        if result.is_err() && !self.context.is_cancelled() {
+            self.cancel_on_ctrl_c(rollback(&self.context)).await;
         }
+        result
+    }
 
-        // Populate initial queue
-        // Move into it's own function
-        let mut queue = VecDeque::new();
-        for steps in all_steps {
-            queue.push_back(steps);
+    /// Awaits the future, cancelling the run when Ctrl-C is pressed. After cancelling the future
+    /// is still awaited so interrupted steps can report their status.
+    async fn cancel_on_ctrl_c<F: Future>(&self, future: F) -> F::Output {
+        tokio::pin!(future);
+        tokio::select! {
+            output = &mut future => return output,
+            _ = tokio::signal::ctrl_c() => self.cancel(),
         }
+        future.await
+    }
+}
 
-        // loop queue until empty
-        // Move into it's own function
-        loop {
-            let mut join_handles = Vec::new();
-
-            let parallel_steps = queue.pop_front();
-            if let Some(parallel_steps) = parallel_steps {
-                for step in parallel_steps {
-                    // Top level StepItems are apart of the "main step sequence"
-                    let mut sequence_name = "main".to_string();
-                    if let Step::Sequence(seq) = &step {
-                        sequence_name = seq.title.clone();
+/// Process a node of the step graph. For a StepItem, manages the standard lifecycle. For a
+/// sequence, processes each batch in order. For a graph, starts every step once its dependencies
+/// completed. For parallel steps, processes them all at once.
+fn process_node<TMH: EventHandler + Send + 'static>(
+    node: StepNode,
+    sequence_name: String,
+    context: ExecutorContext<TMH>,
+) -> BoxFuture<'static, Result<(), Vec<StepProcessError>>> {
+    async move {
+        match node {
+            StepNode::Item(id) => process_step_item(id, &sequence_name, &context)
+                .await
+                .map_err(|e| vec![e]),
+            StepNode::Sequence {
+                title,
+                batches,
+                failure_mode,
+            } => {
+                let context = ExecutorContext {
+                    failure_mode: failure_mode.unwrap_or(context.failure_mode),
+                    ..context
+                };
+                let num_steps = batches.iter().flatten().map(StepNode::num_steps).sum();
+                publish(
+                    &context,
+                    None,
+                    StepEvent::NewSequence(NewSequenceEvent::new(num_steps, &title)),
+                )
+                .await;
+                for batch in batches {
+                    if context.is_cancelled() {
+                        return Err(vec![StepProcessError::new(&title, OecliError::Cancelled)]);
                     }
-                    let event_handler = self.event_handler.clone();
-                    let handle = tokio::spawn(async move {
-                        process_step(step, event_handler, &sequence_name).await
-                    });
-                    join_handles.push(handle);
-                }
-            }
-
-            let results = join_all(join_handles).await;
-            let results = results
-                .into_iter()
-                .map(|r| r.unwrap())
-                .collect::<Vec<Result<Option<Step>, String>>>();
-            for result in &results {
-                if let Err(e) = result {
-                    return Err(e.to_string());
+                    // A step failed alongside this sequence, the failure is reported there.
+                    if context.halt.is_stopped() {
+                        return Ok(());
+                    }
+                    process_batch(batch, &title, &context).await?;
                 }
+                publish(
+                    &context,
+                    None,
+                    StepEvent::EndSequence(&EventData::new("", &title)),
+                )
+                .await;
+                Ok(())
             }
-            let results = results
-                .into_iter()
-                .filter_map(|r| r.unwrap())
-                .collect::<Vec<_>>();
-
-            // We complete depth first, if there are any remaining StepItems to be processed we add
-            // them to the queue.
-            if !results.is_empty() {
-                queue.push_front(results);
+            StepNode::Graph {
+                title,
+                nodes,
+                failure_mode,
+            } => {
+                let context = ExecutorContext {
+                    failure_mode: failure_mode.unwrap_or(context.failure_mode),
+                    ..context
+                };
+                publish(
+                    &context,
+                    None,
+                    StepEvent::NewSequence(NewSequenceEvent::new(nodes.len(), &title)),
+                )
+                .await;
+                graph::process_graph(nodes, &title, &context).await?;
+                publish(
+                    &context,
+                    None,
+                    StepEvent::EndSequence(&EventData::new("", &title)),
+                )
+                .await;
+                Ok(())
             }
+            StepNode::Parallel(nodes) => process_batch(nodes, &sequence_name, &context).await,
+        }
+    }
+    .boxed()
+}
 
-            // Once the queue is empty, we are done.
-            if queue.is_empty() {
-                break;
-            }
+/// Spawns every node in the batch and waits for all of them to complete. Once a node fails, the
+/// [FailureMode] decides whether the other nodes are interrupted, stop starting new steps or run to
+/// their end. Returns every error that occurred.
+async fn process_batch<TMH: EventHandler + Send + 'static>(
+    batch: Vec<StepNode>,
+    sequence_name: &str,
+    context: &ExecutorContext<TMH>,
+) -> Result<(), Vec<StepProcessError>> {
+    let (halt, nested) = context.halt.nest();
+    let nested = ExecutorContext {
+        halt: nested,
+        ..context.clone()
+    };
+    let mut handles: FuturesUnordered<_> = batch
+        .into_iter()
+        .map(|node| {
+            tokio::spawn(process_node(
+                node,
+                sequence_name.to_string(),
+                nested.clone(),
+            ))
+        })
+        .collect();
+    let mut errors = Vec::new();
+    while let Some(result) = handles.next().await {
+        let result = result.unwrap_or_else(|e| {
+            Err(vec![StepProcessError::new(
+                sequence_name,
+                OecliError::Internal(format!("Step task failed to complete. {}", e)),
+            )])
+        });
+        if let Err(e) = result {
+            Halt::raise(&halt, context.failure_mode);
+            errors.extend(e);
         }
+    }
+    if errors.is_empty() {
         Ok(())
+    } else {
+        Err(errors)
     }
 }
 
-/// Process a Step, for a [StepItem](), manages the standard lifecycle. For a [StepSequence](),
-/// will attempt to grab the next "recursive" StepItem from that sequence.
-async fn process_step<TM: EventHandler>(
-    step: Step,
-    event_handler: Arc<Mutex<TM>>,
+/// Logic for managing a [StepItem]() through it's lifecycle. Verifies that the step should be
+/// ran, if it has it will skip. Otherwise will run the main body of the StepItem.
+///
+/// The step only starts once a job and the resources it uses are available.
+///
+/// Both `should_run` and `execute` are interrupted when the step times out or the run is
+/// cancelled.
+async fn process_step_item<TMH: EventHandler + Send + 'static>(
+    id: StepId,
     sequence_name: &str,
-) -> Result<Option<Step>, String> {
-    match step {
-        Step::Step(step_item) => {
-            let step_result =
-                execute_step_item(step_item, event_handler, sequence_name.to_owned()).await;
-            if step_result.is_err() {
-                return Err(step_result.err().unwrap());
+    context: &ExecutorContext<TMH>,
+) -> Result<(), StepProcessError> {
+    let (step_item, details, timeout) = {
+        let mut steps = context.steps.lock().await;
+        let state = match steps.get_mut(&id) {
+            Some(state) => state,
+            None => {
+                return Err(StepProcessError::new(
+                    &id.to_string(),
+                    OecliError::Internal("Step is not registered with the executor.".to_string()),
+                ))
             }
+        };
+        if context.is_cancelled() {
+            return Err(StepProcessError::new(
+                &state.details.title,
+                OecliError::Cancelled,
+            ));
         }
-        Step::Sequence(mut sequence) => {
-            let next_steps = sequence.get_next_sequence_steps();
-
-            if !next_steps.is_empty() {
-                // For each step to be processed we notify the message handler that we are
-                // processing a new sequence.
-                for next_seq_step in &next_steps {
-                    let next_sequence_name = next_seq_step.sequence_name.clone();
-                    {
-                        let num_steps = sequence.num_steps();
-                        let mut event_handler = event_handler.lock().await;
-                        event_handler.handle_event(
-                            None,
-                            StepEvent::NewSequence(NewSequenceEvent::new(
-                                num_steps + 1, // Need to account for popping of the next step
-                                &next_sequence_name,
-                            )),
-                        );
-                    }
-                }
-
-                let mut queue = Vec::new();
-                for next_seq_step in next_steps {
-                    let event_handler = event_handler.clone();
-                    let next_sequence_name = next_seq_step.sequence_name.clone();
-                    queue.push(execute_step_item(
-                        next_seq_step.step,
-                        event_handler,
-                        next_sequence_name,
-                    ));
-                }
-                let results = join_all(queue).await;
-                for result in results {
-                    if result.is_err() {
-                        return Err(result.err().unwrap().to_string());
-                    }
+        // A step failed alongside this one, it is left as not started.
+        if context.halt.is_stopped() {
+            return Ok(());
+        }
+        match state.take() {
+            Some(step_item) => (
+                step_item,
+                state.details.clone(),
+                state.timeout.or(context.step_timeout),
+            ),
+            // Already processed by a previous run.
+            None => {
+                return match state.status {
+                    StepStatus::Completed | StepStatus::Skipped => Ok(()),
+                    status => Err(StepProcessError::new(
+                        &state.details.title,
+                        OecliError::Internal(format!("Step has status {}.", status)),
+                    )),
                 }
-            } else {
-                // No more steps to process, so we are done. Notify the message handler that a
-                // sequence has finished.
-                let mut event_handler = event_handler.lock().await;
-                event_handler.handle_event(
-                    None,
-                    StepEvent::EndSequence(&EventData::new(&sequence.title, sequence_name)),
-                );
-            }
-
-            // If there are any remaining StepItems to be processed, we return them to be added to
-            // the queue.
-            if sequence.has_next() {
-                return Ok(Some(Step::Sequence(sequence)));
             }
         }
+    };
+    if let Some(journal) = &context.journal {
+        let journal = journal.lock().await;
+        if let Some(status @ (StepStatus::Completed | StepStatus::Skipped)) = journal.status(&id) {
+            let msg = format!("Skipped. Completed in run {}.", journal.run_id());
+            drop(journal);
+            start_step(context, &details).await;
+            publish(
+                context,
+                Some(details.clone()),
+                StepEvent::Start(&EventData::new("", sequence_name)),
+            )
+            .await;
+            transition(context, &details, status).await?;
+            publish(
+                context,
+                Some(details),
+                StepEvent::Skip(&EventData::new(&msg, sequence_name)),
+            )
+            .await;
+            return Ok(());
+        }
     }
 
-    // Once a StepItem is processed there are no longer any remaining StepItems to be processed.
-    Ok(None)
-}
-
-/// Logic for managing a [StepItem]() through it's lifecycle. Verifies that the step should be
-/// ran if it has, sill skip. Otherwise will run the main body of the StepItem.
-async fn execute_step_item<TM: EventHandler>(
-    step_item: BoxedStepItem,
-    event_handler: Arc<Mutex<TM>>,
-    sequence_name: String,
-) -> Result<(), String> {
-    let mut event_handler = event_handler.lock().await;
-    let step_details = step_item.get_step_details();
-
-    event_handler.handle_event(
-        Some(step_details.clone()),
-        StepEvent::Start(&EventData::new("", &sequence_name)),
-    );
-
-    let should_run = step_item.should_run().await;
+    // Held until the step is finished. Waiting does not count towards the timeout.
+    let _permits = match interruptible(
+        context,
+        None,
+        None,
+        context.limits.acquire(&step_item.resources()),
+    )
+    .await
+    {
+        Ok(permits) => permits,
+        Err(e) => return fail_step(context, details, sequence_name, e).await,
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    start_step(context, &details).await;
+    publish(
+        context,
+        Some(details.clone()),
+        StepEvent::Start(&EventData::new("", sequence_name)),
+    )
+    .await;
+
+    let should_run = interruptible(
+        context,
+        deadline,
+        timeout,
+        catch_panic(step_item.should_run(&context.run_context)),
+    )
+    .await
+    .and_then(|should_run| should_run);
     match should_run {
-        ShouldRunResult::Ok => {
-            // Should send the name of the sequence to the message handler so it can mark the progress
-            // bar as complete
-            let result = step_item.execute().await;
+        Ok(ShouldRunResult::Ok) => {
+            transition(context, &details, StepStatus::ShouldRun).await?;
+            publish(
+                context,
+                Some(details.clone()),
+                StepEvent::Status(StepStatus::ShouldRun, &EventData::new("", sequence_name)),
+            )
+            .await;
+            transition(context, &details, StepStatus::Running).await?;
+            publish(
+                context,
+                Some(details.clone()),
+                StepEvent::Status(StepStatus::Running, &EventData::new("", sequence_name)),
+            )
+            .await;
+            let (sender, forwarder) = forward_output(context, &details, sequence_name);
+            let result = execute_with_retry(
+                context,
+                &details,
+                sequence_name,
+                &step_item,
+                sender,
+                deadline,
+                timeout,
+            )
+            .await;
+            // Make sure all the output is published before the step ends.
+            let _ = forwarder.await;
             match result {
                 Ok(msg) => {
-                    event_handler.handle_event(
-                        Some(step_details.clone()),
-                        StepEvent::End(&EventData::new(&msg, &sequence_name)),
-                    );
-                    return Ok(());
-                }
-                Err(msg) => {
-                    event_handler.handle_event(
-                        Some(step_details.clone()),
-                        StepEvent::Error(&EventData::new(&msg, &sequence_name)),
-                    );
-                    return Err(msg);
+                    transition(context, &details, StepStatus::Completed).await?;
+                    publish(
+                        context,
+                        Some(details.clone()),
+                        StepEvent::End(&EventData::new(&msg, sequence_name)),
+                    )
+                    .await;
+                    if let Some(completed) = &context.completed {
+                        completed.lock().await.push(CompletedStep {
+                            details,
+                            sequence_name: sequence_name.to_string(),
+                            step_item,
+                        });
+                    }
+                    Ok(())
                 }
+                Err(e) => fail_step(context, details, sequence_name, e).await,
             }
         }
-        ShouldRunResult::Skip => {
-            event_handler.handle_event(
-                Some(step_details.clone()),
+        Ok(ShouldRunResult::Skip) => {
+            skip_step(
+                context,
+                details,
+                sequence_name,
+                "Skipped. Already completed.",
+            )
+            .await
+        }
+        Ok(ShouldRunResult::Unmet(condition)) => {
+            // Left out of the journal, the condition may be met when the run is resumed.
+            set_status(context, &details, StepStatus::Skipped).await;
+            publish(
+                context,
+                Some(details),
                 StepEvent::Skip(&EventData::new(
-                    "Skipped. Already completed.",
-                    &sequence_name,
+                    &format!("Skipped. Only runs if {}.", condition),
+                    sequence_name,
                 )),
-            );
-            return Ok(());
+            )
+            .await;
+            Ok(())
         }
-        ShouldRunResult::Error(e) => {
-            event_handler.handle_event(
-                None,
-                StepEvent::Error(&EventData::new(
-                    &format!("Unexpected error processing step.\n{}", e.to_string()),
-                    &sequence_name,
-                )),
-            );
-            return Err(e);
+        Ok(ShouldRunResult::Error(e)) | Err(e) => {
+            fail_step(context, details, sequence_name, e).await
         }
     }
 }
 
-// New executor
+/// Moves the step to skipped and notifies the event handler.
+async fn skip_step<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    details: StepDetails,
+    sequence_name: &str,
+    msg: &str,
+) -> Result<(), StepProcessError> {
+    transition(context, &details, StepStatus::Skipped).await?;
+    publish(
+        context,
+        Some(details),
+        StepEvent::Skip(&EventData::new(msg, sequence_name)),
+    )
+    .await;
+    Ok(())
+}
 
-// struct that contains the steps to be executed
-//
-// struct that wraps the step to be executed. Contains exists as a mini state machine. Shows the
-// status. Not Started, Should Run, Skipped, Error, Run, Completed.
-//
-// the executor will push all the steps into a queue and tokio spawn them all. The executor will
-// join them all before collecting next set of steps. The executor will also notify the message
-// handler of the status of the steps.
+/// Executes the step, executing it again for every failed attempt its [RetryPolicy] considers
+/// retryable. The timeout covers all attempts together, including the delays between them.
+async fn execute_with_retry<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    details: &StepDetails,
+    sequence_name: &str,
+    step_item: &BoxedStepItem,
+    sender: UnboundedSender<String>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+) -> Result<String, OecliError> {
+    let policy = step_item.retry_policy();
+    let mut attempt = 1;
+    loop {
+        let execute = output::scope(sender.clone(), step_item.execute(&context.run_context));
+        // Dropping the step's future on interrupt kills any process it started.
+        let result = interruptible(context, deadline, timeout, catch_panic(execute)).await;
+        let error = match result.and_then(|result| result) {
+            Ok(Ok(msg)) => return Ok(msg),
+            Ok(Err(e)) | Err(e) => e,
+        };
+        let policy = match &policy {
+            Some(policy) if policy.should_retry(attempt, &error) => policy,
+            _ => return Err(error),
+        };
+        let delay = policy.delay(attempt);
+        publish(
+            context,
+            Some(details.clone()),
+            StepEvent::Retry(&RetryEvent::new(
+                attempt,
+                policy.max_attempts,
+                delay,
+                &error,
+                sequence_name,
+            )),
+        )
+        .await;
+        interruptible(context, deadline, timeout, sleep(delay)).await?;
+        attempt += 1;
+    }
+}
 
-/// Processor for taking a set of steps and executing them in order as described by the Executor Properties.
-pub struct StepExecutor2<TMH: EventHandler> {
-    event_handler: Arc<Mutex<TMH>>,
-    pub steps: Vec<Vec<Step>>,
+/// Moves the step to the status matching the error and notifies the event handler. Timed out and
+/// cancelled steps are published as interrupted, everything else as an error.
+///
+/// A step interrupted because a step alongside it failed is not a failure of its own, it is left
+/// out of the [FailureReport] so the failure that caused it keeps its exit code.
+async fn fail_step<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    details: StepDetails,
+    sequence_name: &str,
+    e: OecliError,
+) -> Result<(), StepProcessError> {
+    let data = EventData::from_error(&e, sequence_name);
+    let (status, event) = match e {
+        OecliError::Timeout(_) => (StepStatus::TimedOut, StepEvent::Interrupt(&data)),
+        OecliError::Cancelled => (StepStatus::Cancelled, StepEvent::Interrupt(&data)),
+        _ => (StepStatus::Error, StepEvent::Error(&data)),
+    };
+    transition(context, &details, status).await?;
+    publish(context, Some(details.clone()), event).await;
+    if matches!(e, OecliError::Cancelled) && !context.is_cancelled() {
+        return Ok(());
+    }
+    Err(StepProcessError::new(&details.title, e))
 }
 
-impl<TMH: EventHandler + Sync + Send + 'static> StepExecutor2<TMH> {
-    /// From [ExecutorProperties]() sets up the steps to be executed.
-    pub fn from_executor_properties(
-        properties: ExecutorProperties,
-        event_handler: TMH,
-    ) -> StepExecutor2<TMH> {
-        StepExecutor2 {
-            event_handler: Arc::new(Mutex::new(event_handler)),
-            steps: properties.get_steps(),
+/// Undoes every step that completed during the run, the most recently completed first. Steps
+/// completed by a previous run are left as they are. Stops when the run is cancelled.
+async fn rollback<TMH: EventHandler + Send + 'static>(context: &ExecutorContext<TMH>) {
+    let completed = match &context.completed {
+        Some(completed) => std::mem::take(&mut *completed.lock().await),
+        None => return,
+    };
+    for step in completed.into_iter().rev() {
+        if context.is_cancelled() {
+            return;
         }
+        let CompletedStep {
+            details,
+            sequence_name,
+            step_item,
+        } = step;
+        let timeout = step_item.timeout().or(context.step_timeout);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let (sender, forwarder) = forward_output(context, &details, &sequence_name);
+        let undo = output::scope(sender, step_item.undo(&context.run_context));
+        let result = interruptible(context, deadline, timeout, catch_panic(undo)).await;
+        let _ = forwarder.await;
+        let result = match result.and_then(|result| result) {
+            Ok(None) => continue,
+            Ok(Some(result)) => result,
+            Err(e) => Err(e),
+        };
+        let result = match result {
+            Ok(msg) => transition(context, &details, StepStatus::RolledBack)
+                .await
+                .map(|_| msg)
+                .map_err(|e| e.error),
+            Err(e) => Err(e),
+        };
+        let data = match &result {
+            Ok(msg) => EventData::new(msg, &sequence_name),
+            Err(e) => EventData::from_error(e, &sequence_name),
+        };
+        publish(context, Some(details), StepEvent::Rollback(&data)).await;
     }
 }
 
-impl<TMH: EventHandler + Sync + Send + 'static> StepExecutor2<TMH> {
-    /// Main processor function. Will push the [StepItems]() into a queue and process each step in the
-    /// queue. As [StepSequences]() are come across, will add the next StepItem to the queue.
-    ///
-    /// Steps will be attempted to be executed asynchronously, either on one thread or many.
-    pub async fn run(self) -> Result<(), String> {
-        // Create a queue of steps to be processed.
+/// Awaits the future unless the deadline passes or the run is cancelled first. Steps interrupted
+/// because a step alongside them failed are cancelled as well.
+async fn interruptible<TMH: EventHandler, F: Future>(
+    context: &ExecutorContext<TMH>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<F::Output, OecliError> {
+    let mut cancel = context.cancel.clone();
+    let timed_out = async {
+        match deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => pending().await,
+        }
+    };
+    let cancelled = async {
+        while !*cancel.borrow() {
+            if cancel.changed().await.is_err() {
+                pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        output = future => Ok(output),
+        _ = timed_out => Err(OecliError::Timeout(timeout.unwrap_or_default())),
+        _ = cancelled => Err(OecliError::Cancelled),
+        _ = context.halt.interrupted() => Err(OecliError::Cancelled),
     }
 }
 
-enum StepStatus {
-    NotStarted,
-    ShouldRun,
-    Skipped,
-    Error,
-    Completed,
+/// Records when the step started, for the [Timings] of the run.
+async fn start_step<TMH: EventHandler>(context: &ExecutorContext<TMH>, details: &StepDetails) {
+    let mut steps = context.steps.lock().await;
+    if let Some(state) = steps.get_mut(&details.id) {
+        state.started = Some(Instant::now());
+    }
 }
 
-impl Display for StepStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            StepStatus::NotStarted => write!(f, "Not Started"),
-            StepStatus::ShouldRun => write!(f, "Should Run"),
-            StepStatus::Skipped => write!(f, "Skipped"),
-            StepStatus::Error => write!(f, "Error"),
-            StepStatus::Completed => write!(f, "Completed"),
+/// Moves the step to the new status. Once a step is finished the status is recorded in the
+/// journal, along with the values in the [RunContext].
+async fn transition<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    details: &StepDetails,
+    status: StepStatus,
+) -> Result<(), StepProcessError> {
+    set_status(context, details, status).await;
+    if let Some(journal) = &context.journal {
+        if status.is_finished() {
+            journal
+                .lock()
+                .await
+                .record(details, status, context.run_context.values())
+                .await
+                .map_err(|e| StepProcessError::new(&details.title, e))?;
         }
     }
+    Ok(())
 }
 
-struct StepState {
-    step: BoxedStepItem,
+/// Moves the step to the new status without recording it in the journal.
+async fn set_status<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    details: &StepDetails,
     status: StepStatus,
-}
-
-impl StepState {
-    fn new(step: BoxedStepItem) -> StepState {
-        StepState {
-            step,
-            status: StepStatus::NotStarted,
+) {
+    let mut steps = context.steps.lock().await;
+    if let Some(state) = steps.get_mut(&details.id) {
+        state.status = status;
+        if status.is_finished() && state.finished.is_none() {
+            state.finished = Some(Instant::now());
         }
     }
 }
 
-impl StepState {
-    async fn run(mut self) -> StepState {
-        match self.status {
-            StepStatus::NotStarted => {
-                let result = self.step.should_run().await;
-                match result {
-                    ShouldRunResult::Ok => {
-                        self.status = StepStatus::ShouldRun;
-                    }
-                    ShouldRunResult::Skip => {
-                        self.status = StepStatus::Skipped;
-                    }
-                    ShouldRunResult::Error(e) => {
-                        self.status = StepStatus::Error;
-                    }
-                }
-                self.status = StepStatus::ShouldRun;
-            }
-            StepStatus::ShouldRun => {
-                self.status = StepStatus::Completed;
-            }
-            _ => {}
+/// Sets up the channel the output of the step is sent through. Every line is published as an
+/// output event until the sender is dropped.
+fn forward_output<TMH: EventHandler + Send + 'static>(
+    context: &ExecutorContext<TMH>,
+    details: &StepDetails,
+    sequence_name: &str,
+) -> (UnboundedSender<String>, JoinHandle<()>) {
+    let (sender, mut receiver) = unbounded_channel::<String>();
+    let context = context.clone();
+    let details = details.clone();
+    let sequence_name = sequence_name.to_string();
+    let forwarder = tokio::spawn(async move {
+        while let Some(line) = receiver.recv().await {
+            publish(
+                &context,
+                Some(details.clone()),
+                StepEvent::Output(&EventData::new(&line, &sequence_name)),
+            )
+            .await;
         }
-        self
-    }
+    });
+    (sender, forwarder)
+}
+
+/// Notifies the event handler. The lock is only held while the event is handled so other steps
+/// are free to run.
+async fn publish<TMH: EventHandler>(
+    context: &ExecutorContext<TMH>,
+    step: Option<StepDetails>,
+    event: StepEvent<'_>,
+) {
+    let mut event_handler = context.event_handler.lock().await;
+    event_handler.handle_event(step, event);
 }
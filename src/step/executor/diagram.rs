@@ -0,0 +1,214 @@
+//! A diagram of the step graph, rendered as Graphviz or Mermaid without running any steps.
+//!
+//! Every step item is a node labelled with its title. Sequences and graphs are drawn as clusters
+//! around their steps. Edges follow the order steps run in: every step of a batch leads to every
+//! step the next batch starts with, and within a graph every dependency leads to its dependents.
+
+use super::StepNode;
+use crate::step::Steps;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The formats a [Diagram] can be rendered in.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiagramFormat {
+    /// Graphviz, render it with `dot -Tsvg`.
+    Dot,
+    /// Mermaid flowchart, rendered by GitHub within markdown.
+    Mermaid,
+}
+
+enum DiagramNode {
+    /// The index of the step, which names the node, and its title.
+    Step(usize, String),
+    /// A sequence or graph and the nodes within it.
+    Cluster(String, Vec<DiagramNode>),
+}
+
+/// The step graph reduced to step titles, clusters and the edges between steps.
+pub struct Diagram {
+    nodes: Vec<DiagramNode>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl Diagram {
+    pub(super) fn build(root: &StepNode, steps: &Steps) -> Diagram {
+        let mut diagram = Diagram {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut count = 0;
+        let mut nodes = Vec::new();
+        diagram.add(root, steps, &mut count, &mut nodes);
+        diagram.nodes = nodes;
+        diagram
+    }
+
+    /// Adds the node to `nodes`, numbering its steps from `count`. Returns the steps the node
+    /// starts with and the steps it ends with, so the caller can connect them to the steps
+    /// around the node.
+    fn add(
+        &mut self,
+        node: &StepNode,
+        steps: &Steps,
+        count: &mut usize,
+        nodes: &mut Vec<DiagramNode>,
+    ) -> (Vec<usize>, Vec<usize>) {
+        match node {
+            StepNode::Item(id) => {
+                let title = steps
+                    .get(id)
+                    .map(|state| state.details.title.clone())
+                    .unwrap_or_else(|| id.to_string());
+                let index = *count;
+                *count += 1;
+                nodes.push(DiagramNode::Step(index, title));
+                (vec![index], vec![index])
+            }
+            StepNode::Sequence { title, batches, .. } => {
+                let mut cluster = Vec::new();
+                let mut first = None;
+                let mut last = Vec::new();
+                for batch in batches {
+                    let (entries, exits) = self.add_batch(batch, steps, count, &mut cluster);
+                    // Empty batches and sequences do not break the chain.
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    for from in &last {
+                        for to in &entries {
+                            self.edges.push((*from, *to));
+                        }
+                    }
+                    first.get_or_insert(entries);
+                    last = exits;
+                }
+                nodes.push(DiagramNode::Cluster(title.clone(), cluster));
+                (first.unwrap_or_default(), last)
+            }
+            StepNode::Graph {
+                title,
+                nodes: graph,
+                ..
+            } => {
+                let mut cluster = Vec::new();
+                let mut index_of = HashMap::new();
+                for (id, _) in graph {
+                    let (entries, _) = self.add(&StepNode::Item(*id), steps, count, &mut cluster);
+                    index_of.insert(*id, entries[0]);
+                }
+                for (id, dependencies) in graph {
+                    for dependency in dependencies {
+                        if let Some(from) = index_of.get(dependency) {
+                            self.edges.push((*from, index_of[id]));
+                        }
+                    }
+                }
+                let entries = graph
+                    .iter()
+                    .filter(|(_, dependencies)| dependencies.is_empty())
+                    .map(|(id, _)| index_of[id])
+                    .collect();
+                // Steps no other step of the graph depends on.
+                let exits = graph
+                    .iter()
+                    .filter(|(id, _)| !graph.iter().any(|(_, other)| other.contains(id)))
+                    .map(|(id, _)| index_of[id])
+                    .collect();
+                nodes.push(DiagramNode::Cluster(title.clone(), cluster));
+                (entries, exits)
+            }
+            StepNode::Parallel(parallel) => self.add_batch(parallel, steps, count, nodes),
+        }
+    }
+
+    /// The nodes of a batch start and end together.
+    fn add_batch(
+        &mut self,
+        batch: &[StepNode],
+        steps: &Steps,
+        count: &mut usize,
+        nodes: &mut Vec<DiagramNode>,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let mut entries = Vec::new();
+        let mut exits = Vec::new();
+        for node in batch {
+            let (node_entries, node_exits) = self.add(node, steps, count, nodes);
+            entries.extend(node_entries);
+            exits.extend(node_exits);
+        }
+        (entries, exits)
+    }
+
+    pub fn render(&self, format: DiagramFormat) -> String {
+        let mut out = String::new();
+        match format {
+            DiagramFormat::Dot => {
+                out.push_str("digraph steps {\n  rankdir=TB;\n  node [shape=box];\n");
+                let mut clusters = 0;
+                for node in &self.nodes {
+                    write_dot(&mut out, node, 1, &mut clusters);
+                }
+                for (from, to) in &self.edges {
+                    let _ = writeln!(out, "  s{} -> s{};", from, to);
+                }
+                out.push('}');
+            }
+            DiagramFormat::Mermaid => {
+                out.push_str("flowchart TD\n");
+                let mut clusters = 0;
+                for node in &self.nodes {
+                    write_mermaid(&mut out, node, 1, &mut clusters);
+                }
+                for (from, to) in &self.edges {
+                    let _ = writeln!(out, "  s{} --> s{}", from, to);
+                }
+                out.pop();
+            }
+        }
+        out
+    }
+}
+
+fn write_dot(out: &mut String, node: &DiagramNode, depth: usize, clusters: &mut usize) {
+    let indent = "  ".repeat(depth);
+    let escape = |label: &str| label.replace('\\', "\\\\").replace('"', "\\\"");
+    match node {
+        DiagramNode::Step(index, title) => {
+            let _ = writeln!(out, "{}s{} [label=\"{}\"];", indent, index, escape(title));
+        }
+        DiagramNode::Cluster(title, nodes) => {
+            let _ = writeln!(out, "{}subgraph cluster_{} {{", indent, clusters);
+            let _ = writeln!(out, "{}  label=\"{}\";", indent, escape(title));
+            *clusters += 1;
+            for node in nodes {
+                write_dot(out, node, depth + 1, clusters);
+            }
+            let _ = writeln!(out, "{}}}", indent);
+        }
+    }
+}
+
+fn write_mermaid(out: &mut String, node: &DiagramNode, depth: usize, clusters: &mut usize) {
+    let indent = "  ".repeat(depth);
+    let escape = |label: &str| label.replace('"', "#quot;");
+    match node {
+        DiagramNode::Step(index, title) => {
+            let _ = writeln!(out, "{}s{}[\"{}\"]", indent, index, escape(title));
+        }
+        DiagramNode::Cluster(title, nodes) => {
+            let _ = writeln!(
+                out,
+                "{}subgraph c{}[\"{}\"]",
+                indent,
+                clusters,
+                escape(title)
+            );
+            *clusters += 1;
+            for node in nodes {
+                write_mermaid(out, node, depth + 1, clusters);
+            }
+            let _ = writeln!(out, "{}end", indent);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! How the executor reacts when a step fails, and the report of every failure once the run is
+//! over.
+
+use crate::step::StepProcessError;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::future::pending;
+use tokio::sync::watch;
+
+/// What happens to the other steps of a batch once one of them fails. The batches after the
+/// failed one never run.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureMode {
+    /// Interrupt the steps of the batch that are still in progress.
+    #[default]
+    FailFast,
+    /// Let the steps in progress finish, but do not start any new step within the batch.
+    FinishInFlight,
+    /// Let every other branch of the batch run to its end.
+    Continue,
+}
+
+impl FailureMode {
+    fn halt_level(&self) -> HaltLevel {
+        match self {
+            FailureMode::FailFast => HaltLevel::Interrupt,
+            FailureMode::FinishInFlight => HaltLevel::Stop,
+            FailureMode::Continue => HaltLevel::Running,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(super) enum HaltLevel {
+    Running,
+    /// Do not start new steps.
+    Stop,
+    /// Interrupt the steps in progress as well.
+    Interrupt,
+}
+
+/// Signals raised by the batches a node is nested in once one of their steps fails.
+#[derive(Clone, Default)]
+pub(super) struct Halt {
+    signals: Vec<watch::Receiver<HaltLevel>>,
+}
+
+impl Halt {
+    /// Adds the signal of a batch nested within the current one. The sender raises it.
+    pub fn nest(&self) -> (watch::Sender<HaltLevel>, Halt) {
+        let (sender, receiver) = watch::channel(HaltLevel::Running);
+        let mut signals = self.signals.clone();
+        signals.push(receiver);
+        (sender, Halt { signals })
+    }
+
+    /// Raises the signal as far as the failure mode asks for.
+    pub fn raise(sender: &watch::Sender<HaltLevel>, mode: FailureMode) {
+        let level = mode.halt_level();
+        if level > *sender.borrow() {
+            let _ = sender.send(level);
+        }
+    }
+
+    /// No new steps should be started.
+    pub fn is_stopped(&self) -> bool {
+        self.signals
+            .iter()
+            .any(|signal| *signal.borrow() >= HaltLevel::Stop)
+    }
+
+    /// Resolves once steps in progress should be interrupted.
+    pub async fn interrupted(&self) {
+        let mut waiting: FuturesUnordered<_> = self
+            .signals
+            .iter()
+            .cloned()
+            .map(|mut signal| async move {
+                while *signal.borrow() != HaltLevel::Interrupt {
+                    if signal.changed().await.is_err() {
+                        pending::<()>().await;
+                    }
+                }
+            })
+            .collect();
+        if waiting.next().await.is_none() {
+            pending::<()>().await;
+        }
+    }
+}
+
+/// Every step that failed during a run, in the order they failed.
+#[derive(Clone, Debug)]
+pub struct FailureReport {
+    pub failures: Vec<StepProcessError>,
+}
+
+impl Display for FailureReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let [failure] = self.failures.as_slice() {
+            return write!(f, "{}", failure);
+        }
+        write!(f, "{} steps failed:", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n- {}", failure)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Schedules the steps of a [StepGraph](crate::step::StepGraph). Every step starts as soon as all
+//! of its dependencies have completed.
+
+use super::{process_step_item, ExecutorContext, Halt};
+use crate::error::OecliError;
+use crate::step::msg_handler::EventHandler;
+use crate::step::{StepId, StepProcessError, Steps};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{HashMap, HashSet};
+
+/// A step of the graph and the ids of the steps it depends on.
+pub(super) type GraphNode = (StepId, Vec<StepId>);
+
+/// Runs every step of the graph once its dependencies have completed. When a step fails the steps
+/// depending on it never run, the [FailureMode](crate::step::FailureMode) decides what happens to
+/// the other steps. Returns every error that occurred.
+pub(super) async fn process_graph<TMH: EventHandler + Send + 'static>(
+    nodes: Vec<GraphNode>,
+    title: &str,
+    context: &ExecutorContext<TMH>,
+) -> Result<(), Vec<StepProcessError>> {
+    let (halt, nested) = context.halt.nest();
+    let nested = ExecutorContext {
+        halt: nested,
+        ..context.clone()
+    };
+    let mut waiting: Vec<(StepId, HashSet<StepId>)> = nodes
+        .into_iter()
+        .map(|(id, dependencies)| (id, dependencies.into_iter().collect()))
+        .collect();
+    let mut running = FuturesUnordered::new();
+    let mut errors = Vec::new();
+    loop {
+        // Start every step whose dependencies have all completed.
+        waiting.retain(|(id, dependencies)| {
+            if !dependencies.is_empty() {
+                return true;
+            }
+            let id = *id;
+            let title = title.to_string();
+            let context = nested.clone();
+            running.push(tokio::spawn(async move {
+                (id, process_step_item(id, &title, &context).await)
+            }));
+            false
+        });
+        let (id, result) = match running.next().await {
+            Some(Ok(finished)) => finished,
+            Some(Err(e)) => {
+                Halt::raise(&halt, context.failure_mode);
+                errors.push(StepProcessError::new(
+                    title,
+                    OecliError::Internal(format!("Step task failed to complete. {}", e)),
+                ));
+                continue;
+            }
+            None => break,
+        };
+        match result {
+            Ok(()) => {
+                for (_, dependencies) in waiting.iter_mut() {
+                    dependencies.remove(&id);
+                }
+            }
+            Err(e) => {
+                Halt::raise(&halt, context.failure_mode);
+                errors.push(e);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that every dependency is part of the graph and that no steps depend on each other.
+/// Returns the ids in an order where every step comes after its dependencies.
+pub(super) fn topological_order(nodes: &[GraphNode], steps: &Steps) -> Result<Vec<StepId>, String> {
+    let title = |id: &StepId| {
+        steps
+            .get(id)
+            .map_or_else(|| id.to_string(), |state| state.details.title.clone())
+    };
+    let mut dependencies: HashMap<StepId, HashSet<StepId>> = HashMap::new();
+    for (id, depends_on) in nodes {
+        if dependencies
+            .insert(*id, depends_on.iter().copied().collect())
+            .is_some()
+        {
+            return Err(format!("Step {} is added more than once.", title(id)));
+        }
+    }
+    for (id, depends_on) in nodes {
+        if let Some(missing) = depends_on.iter().find(|d| !dependencies.contains_key(d)) {
+            return Err(format!(
+                "Step {} depends on {}, which is not part of the graph.",
+                title(id),
+                missing
+            ));
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut remaining: Vec<StepId> = nodes.iter().map(|(id, _)| *id).collect();
+    while !remaining.is_empty() {
+        let (ready, blocked): (Vec<StepId>, Vec<StepId>) = remaining
+            .iter()
+            .copied()
+            .partition(|id| dependencies[id].iter().all(|d| order.contains(d)));
+        if ready.is_empty() {
+            let cycle = find_cycle(&blocked, &dependencies);
+            let titles: Vec<String> = cycle.iter().map(title).collect();
+            return Err(format!(
+                "Steps depend on each other: {}.",
+                titles.join(" -> ")
+            ));
+        }
+        order.extend(ready);
+        remaining = blocked;
+    }
+    Ok(order)
+}
+
+/// Every blocked step depends on another blocked step, so following the dependencies ends up in a
+/// cycle. Returns the steps of that cycle, starting and ending with the same step.
+fn find_cycle(blocked: &[StepId], dependencies: &HashMap<StepId, HashSet<StepId>>) -> Vec<StepId> {
+    let mut path = vec![blocked[0]];
+    loop {
+        let current = path[path.len() - 1];
+        let next = dependencies[&current]
+            .iter()
+            .copied()
+            .filter(|d| blocked.contains(d))
+            .min()
+            .unwrap_or(current);
+        if let Some(start) = path.iter().position(|id| *id == next) {
+            let mut cycle = path.split_off(start);
+            cycle.push(next);
+            return cycle;
+        }
+        path.push(next);
+    }
+}
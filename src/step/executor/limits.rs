@@ -0,0 +1,56 @@
+//! Semaphores bounding how many steps run at once, overall and per resource.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits shared by every step of the run.
+#[derive(Clone, Default)]
+pub(super) struct Limits {
+    /// Limits the number of steps running at once.
+    jobs: Option<Arc<Semaphore>>,
+    resources: HashMap<String, Arc<Semaphore>>,
+}
+
+impl Limits {
+    pub fn new(jobs: Option<usize>, resources: &BTreeMap<String, usize>) -> Limits {
+        Limits {
+            jobs: jobs.map(|jobs| Arc::new(Semaphore::new(jobs))),
+            resources: resources
+                .iter()
+                .map(|(name, limit)| (name.clone(), Arc::new(Semaphore::new(*limit))))
+                .collect(),
+        }
+    }
+
+    /// Waits until a job and every limited resource the step uses are available. The permits are
+    /// released once they are dropped.
+    ///
+    /// Permits are always acquired in the same order, so steps waiting on each other can not
+    /// deadlock.
+    pub async fn acquire(&self, resources: &[String]) -> Vec<OwnedSemaphorePermit> {
+        let mut semaphores: Vec<(&str, &Arc<Semaphore>)> = resources
+            .iter()
+            .filter_map(|name| {
+                self.resources
+                    .get_key_value(name)
+                    .map(|(name, semaphore)| (name.as_str(), semaphore))
+            })
+            .collect();
+        semaphores.sort_by_key(|(name, _)| *name);
+        semaphores.dedup_by_key(|(name, _)| *name);
+
+        let mut permits = Vec::new();
+        let semaphores = self
+            .jobs
+            .iter()
+            .chain(semaphores.into_iter().map(|(_, semaphore)| semaphore));
+        for semaphore in semaphores {
+            // The semaphores are never closed.
+            if let Ok(permit) = semaphore.clone().acquire_owned().await {
+                permits.push(permit);
+            }
+        }
+        permits
+    }
+}
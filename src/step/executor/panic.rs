@@ -0,0 +1,67 @@
+//! A panic within a step fails that step rather than taking down the run. The panic is caught
+//! where the executor polls the step, and reported as an [OecliError::Panic] naming where it
+//! happened. The failure mode of the sequence decides what happens to the other steps, like it
+//! does for any other error.
+//!
+//! Panics within a step are not printed by the panic hook, that would break the progress bars.
+//! Panics anywhere else are passed on to the hook that was installed before.
+
+use crate::error::OecliError;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::future::{poll_fn, Future};
+use std::panic::AssertUnwindSafe;
+use std::sync::Once;
+use std::task::Poll;
+
+thread_local! {
+    /// Set while this thread polls the future of a step.
+    static IN_STEP: Cell<bool> = const { Cell::new(false) };
+    /// Where the last panic within a step on this thread happened.
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if IN_STEP.with(Cell::get) {
+                let location = info.location().map(|location| location.to_string());
+                LOCATION.with(|last| *last.borrow_mut() = location);
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Polls the future, turning a panic while polling it into an error.
+pub(super) async fn catch_panic<F: Future>(future: F) -> Result<F::Output, OecliError> {
+    install_hook();
+    let mut future = std::pin::pin!(future);
+    poll_fn(|cx| {
+        let outer = IN_STEP.with(|in_step| in_step.replace(true));
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx)));
+        IN_STEP.with(|in_step| in_step.set(outer));
+        match result {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(panic_error(payload))),
+        }
+    })
+    .await
+}
+
+fn panic_error(payload: Box<dyn Any + Send>) -> OecliError {
+    let msg = match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "Unknown cause.".to_string(),
+        },
+    };
+    let location = LOCATION.with(|last| last.borrow_mut().take());
+    OecliError::Panic { msg, location }
+}
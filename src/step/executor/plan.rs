@@ -0,0 +1,241 @@
+//! A plan describes what the executor would do without executing any steps. Only `should_run` is
+//! called for each step.
+//!
+//! Since no step executes, the run context only holds the values a resumed run left behind. A
+//! step that needs a value an earlier step would set can not tell what it would do, it is
+//! planned as depending on values produced at run time rather than as an error.
+
+use super::panic::catch_panic;
+use super::{graph, StepNode};
+use crate::error::OecliError;
+use crate::step::{RunContext, RunJournal, ShouldRunResult, StepId, StepStatus, Steps};
+use futures::future::{BoxFuture, FutureExt};
+use std::fmt::{Display, Formatter};
+
+/// What would happen to a single step if the executor ran.
+pub enum PlanOutcome {
+    /// `should_run` returned ok, the step would be executed.
+    Run,
+    /// The step would be skipped, either by `should_run` or by the run journal.
+    Skip(String),
+    /// `should_run` needs a value that an earlier step sets when it runs.
+    Pending(String),
+    /// `should_run` returned an error, the run would stop here.
+    Error(String),
+}
+
+/// The step graph annotated with the outcome of each step.
+pub enum Plan {
+    Step {
+        title: String,
+        outcome: PlanOutcome,
+    },
+    Sequence {
+        title: String,
+        batches: Vec<Vec<Plan>>,
+    },
+    /// Steps of a graph in the order they could run, with the titles of their dependencies.
+    Graph {
+        title: String,
+        steps: Vec<(Plan, Vec<String>)>,
+    },
+    Parallel(Vec<Plan>),
+}
+
+impl Plan {
+    /// Evaluates `should_run` for every step in the node.
+    pub(super) fn build<'a>(
+        node: &'a StepNode,
+        steps: &'a Steps,
+        journal: Option<&'a RunJournal>,
+        ctx: &'a RunContext,
+    ) -> BoxFuture<'a, Plan> {
+        async move {
+            match node {
+                StepNode::Item(id) => Plan::build_item(id, steps, journal, ctx).await,
+                StepNode::Sequence { title, batches, .. } => {
+                    let mut planned = Vec::new();
+                    for batch in batches {
+                        let mut planned_batch = Vec::new();
+                        for node in batch {
+                            planned_batch.push(Plan::build(node, steps, journal, ctx).await);
+                        }
+                        planned.push(planned_batch);
+                    }
+                    Plan::Sequence {
+                        title: title.clone(),
+                        batches: planned,
+                    }
+                }
+                StepNode::Graph { title, nodes, .. } => {
+                    let order = match graph::topological_order(nodes, steps) {
+                        Ok(order) => order,
+                        Err(msg) => {
+                            return Plan::Step {
+                                title: title.clone(),
+                                outcome: PlanOutcome::Error(msg),
+                            }
+                        }
+                    };
+                    let mut planned = Vec::new();
+                    for id in order {
+                        let after = nodes
+                            .iter()
+                            .find(|(node, _)| *node == id)
+                            .map(|(_, dependencies)| {
+                                dependencies
+                                    .iter()
+                                    .filter_map(|d| steps.get(d))
+                                    .map(|state| state.details.title.clone())
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        planned.push((Plan::build_item(&id, steps, journal, ctx).await, after));
+                    }
+                    Plan::Graph {
+                        title: title.clone(),
+                        steps: planned,
+                    }
+                }
+                StepNode::Parallel(nodes) => {
+                    let mut planned = Vec::new();
+                    for node in nodes {
+                        planned.push(Plan::build(node, steps, journal, ctx).await);
+                    }
+                    Plan::Parallel(planned)
+                }
+            }
+        }
+        .boxed()
+    }
+
+    async fn build_item(
+        id: &StepId,
+        steps: &Steps,
+        journal: Option<&RunJournal>,
+        ctx: &RunContext,
+    ) -> Plan {
+        let state = match steps.get(id) {
+            Some(state) => state,
+            None => {
+                return Plan::Step {
+                    title: id.to_string(),
+                    outcome: PlanOutcome::Error(
+                        "Step is not registered with the executor.".to_string(),
+                    ),
+                }
+            }
+        };
+        let title = state.details.title.clone();
+        let journal_status = journal.and_then(|journal| journal.status(id));
+        if let Some(StepStatus::Completed | StepStatus::Skipped) = journal_status {
+            let run_id = journal.map(RunJournal::run_id).unwrap_or_default();
+            return Plan::Step {
+                title,
+                outcome: PlanOutcome::Skip(format!("Completed in run {}.", run_id)),
+            };
+        }
+        let outcome = match state.step() {
+            Some(step_item) => match catch_panic(step_item.should_run(ctx)).await {
+                Ok(ShouldRunResult::Ok) => PlanOutcome::Run,
+                Ok(ShouldRunResult::Skip) => PlanOutcome::Skip("Already completed.".to_string()),
+                Ok(ShouldRunResult::Unmet(condition)) => {
+                    PlanOutcome::Skip(format!("Only runs if {}.", condition))
+                }
+                Ok(ShouldRunResult::Error(OecliError::Unset(msg))) => PlanOutcome::Pending(msg),
+                Ok(ShouldRunResult::Error(e)) | Err(e) => PlanOutcome::Error(e.to_string()),
+            },
+            None => PlanOutcome::Skip(format!("Already {}.", state.status)),
+        };
+        Plan::Step { title, outcome }
+    }
+
+    /// Counts the steps that would run, be skipped, error and that depend on values produced at
+    /// run time.
+    pub fn totals(&self) -> (usize, usize, usize, usize) {
+        match self {
+            Plan::Step { outcome, .. } => match outcome {
+                PlanOutcome::Run => (1, 0, 0, 0),
+                PlanOutcome::Skip(_) => (0, 1, 0, 0),
+                PlanOutcome::Error(_) => (0, 0, 1, 0),
+                PlanOutcome::Pending(_) => (0, 0, 0, 1),
+            },
+            Plan::Sequence { batches, .. } => Plan::sum(batches.iter().flatten()),
+            Plan::Graph { steps, .. } => Plan::sum(steps.iter().map(|(plan, _)| plan)),
+            Plan::Parallel(plans) => Plan::sum(plans.iter()),
+        }
+    }
+
+    fn sum<'a>(plans: impl Iterator<Item = &'a Plan>) -> (usize, usize, usize, usize) {
+        plans.map(Plan::totals).fold((0, 0, 0, 0), |acc, totals| {
+            (
+                acc.0 + totals.0,
+                acc.1 + totals.1,
+                acc.2 + totals.2,
+                acc.3 + totals.3,
+            )
+        })
+    }
+
+    fn write_indented(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            Plan::Step { title, outcome } => match outcome {
+                PlanOutcome::Run => writeln!(f, "{}[run]   {}", indent, title),
+                PlanOutcome::Skip(msg) => writeln!(f, "{}[skip]  {} ({})", indent, title, msg),
+                PlanOutcome::Error(msg) => writeln!(f, "{}[error] {}: {}", indent, title, msg),
+                PlanOutcome::Pending(msg) => writeln!(
+                    f,
+                    "{}[later] {} (Depends on values produced at run time. {})",
+                    indent, title, msg
+                ),
+            },
+            Plan::Graph { title, steps } => {
+                writeln!(f, "{}Graph: {}", indent, title)?;
+                for (plan, after) in steps {
+                    plan.write_indented(f, depth + 1)?;
+                    if !after.is_empty() {
+                        writeln!(f, "{}  after: {}", "  ".repeat(depth + 1), after.join(", "))?;
+                    }
+                }
+                Ok(())
+            }
+            Plan::Sequence { title, batches } => {
+                writeln!(f, "{}Sequence: {}", indent, title)?;
+                for batch in batches {
+                    Plan::write_batch(f, batch, depth + 1)?;
+                }
+                Ok(())
+            }
+            Plan::Parallel(plans) => Plan::write_batch(f, plans, depth),
+        }
+    }
+
+    /// Batches with more than one step are grouped under a parallel heading.
+    fn write_batch(f: &mut Formatter<'_>, batch: &[Plan], depth: usize) -> std::fmt::Result {
+        if batch.len() == 1 {
+            return batch[0].write_indented(f, depth);
+        }
+        writeln!(f, "{}Parallel:", "  ".repeat(depth))?;
+        for plan in batch {
+            plan.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write_indented(f, 0)?;
+        let (run, skip, error, pending) = self.totals();
+        write!(
+            f,
+            "\n{} step(s) would run, {} would be skipped, {} would error.",
+            run, skip, error
+        )?;
+        if pending > 0 {
+            write!(f, " {} depend on values produced at run time.", pending)?;
+        }
+        Ok(())
+    }
+}
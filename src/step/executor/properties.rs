@@ -1,17 +1,83 @@
 //!  ExecutorProperties defines the behaviors of the Step Executor.
 
-use crate::step::Step;
+use crate::step::{resource, Condition, FailureMode, Step};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// Describes a way to define a series of steps to be executed and in which order or which can be
 /// one asynchronously. ExecutorProperties are provided in the CommandExecutor and used to
 /// construct the steps and execute them.
 pub struct ExecutorProperties {
+    failure_mode: FailureMode,
+    jobs: Option<usize>,
+    resource_limits: BTreeMap<String, usize>,
+    rollback_on_failure: bool,
+    step_timeout: Option<Duration>,
     steps: Vec<Vec<Step>>,
 }
 
 impl ExecutorProperties {
     pub fn new() -> ExecutorProperties {
-        ExecutorProperties { steps: Vec::new() }
+        ExecutorProperties {
+            failure_mode: FailureMode::default(),
+            jobs: None,
+            resource_limits: resource::default_limits(),
+            rollback_on_failure: false,
+            step_timeout: None,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Interrupt any step that takes longer than the timeout. Steps can declare their own
+    /// timeout, which takes precedence.
+    pub fn with_step_timeout(mut self, timeout: Duration) -> ExecutorProperties {
+        self.step_timeout = Some(timeout);
+        self
+    }
+
+    pub fn get_step_timeout(&self) -> Option<Duration> {
+        self.step_timeout
+    }
+
+    /// How the run reacts to a failed step. Sequences can set their own failure mode.
+    pub fn with_failure_mode(mut self, failure_mode: FailureMode) -> ExecutorProperties {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    pub fn get_failure_mode(&self) -> FailureMode {
+        self.failure_mode
+    }
+
+    /// Run at most this many steps at once.
+    pub fn with_jobs(mut self, jobs: usize) -> ExecutorProperties {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    pub fn get_jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+
+    /// Run at most `limit` steps that use the resource at once. Replaces the default limit of the
+    /// resource.
+    pub fn with_resource_limit(mut self, resource: &str, limit: usize) -> ExecutorProperties {
+        self.resource_limits.insert(resource.to_string(), limit);
+        self
+    }
+
+    pub fn get_resource_limits(&self) -> &BTreeMap<String, usize> {
+        &self.resource_limits
+    }
+
+    /// Undo the steps completed during the run, in reverse order, when a step fails.
+    pub fn with_rollback_on_failure(mut self) -> ExecutorProperties {
+        self.rollback_on_failure = true;
+        self
+    }
+
+    pub fn get_rollback_on_failure(&self) -> bool {
+        self.rollback_on_failure
     }
 
     /// Set the step to be ran.
@@ -35,6 +101,13 @@ impl ExecutorProperties {
         self
     }
 
+    /// Will add a step to run after the previous step(s) were completed, only when the condition
+    /// holds.
+    #[allow(dead_code)]
+    pub fn then_run_if(self, step: Step, condition: Condition) -> ExecutorProperties {
+        self.then_run(step.run_if(condition))
+    }
+
     /// Will add several steps to run after the previous step(s) were
     /// completed.
     #[allow(dead_code)]
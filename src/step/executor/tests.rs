@@ -0,0 +1,675 @@
+//! Tests of the executor against fake steps. Time is paused, so sleeping steps finish at exact
+//! and repeatable times.
+
+use super::{DiagramFormat, FailureMode, FailureReport, StepExecutor};
+use crate::error::OecliError;
+use crate::step::testing::{FakeStep, Recorded, RecordingEventHandler, TempDir};
+use crate::step::{
+    Backoff, Condition, ContextKey, ExecutorProperties, RetryPolicy, RunJournal, Step, StepGraph,
+    StepId, StepSequence, StepStatus,
+};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn run(properties: ExecutorProperties) -> (Result<(), FailureReport>, RecordingEventHandler) {
+    let recorder = RecordingEventHandler::new();
+    let executor = StepExecutor::from_executor_properties(properties, recorder.clone());
+    (executor.run().await, recorder)
+}
+
+fn start(title: &str) -> Recorded {
+    Recorded::Start(title.to_string())
+}
+
+fn end(title: &str) -> Recorded {
+    Recorded::End(title.to_string())
+}
+
+fn millis(millis: u64) -> Option<Duration> {
+    Some(Duration::from_millis(millis))
+}
+
+#[tokio::test(start_paused = true)]
+async fn runs_steps_of_a_sequence_in_order() {
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").sleep(100).step())
+        .then_run(FakeStep::succeed("b").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        recorder.lifecycle(),
+        vec![
+            Recorded::SequenceStart("main".to_string(), 2),
+            start("a"),
+            end("a"),
+            start("b"),
+            end("b"),
+            Recorded::SequenceEnd("main".to_string()),
+        ]
+    );
+    assert_eq!(recorder.at(&start("b")), millis(100));
+}
+
+#[tokio::test(start_paused = true)]
+async fn publishes_status_changes_between_start_and_end() {
+    let properties = ExecutorProperties::new().then_run(FakeStep::succeed("a").step());
+    let (_, recorder) = run(properties).await;
+
+    let events: Vec<_> = recorder
+        .timed()
+        .into_iter()
+        .map(|(_, event)| event)
+        .filter(|event| {
+            !matches!(
+                event,
+                Recorded::SequenceStart(..) | Recorded::SequenceEnd(_)
+            )
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            start("a"),
+            Recorded::Status("a".to_string(), StepStatus::ShouldRun),
+            Recorded::Status("a".to_string(), StepStatus::Running),
+            end("a"),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn runs_a_parallel_batch_at_once() {
+    let properties = ExecutorProperties::new()
+        .then_run_parallel(vec![
+            FakeStep::succeed("a").sleep(300).step(),
+            FakeStep::succeed("b").sleep(100).step(),
+        ])
+        .then_run(FakeStep::succeed("c").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    assert_eq!(recorder.at(&start("a")), millis(0));
+    assert_eq!(recorder.at(&start("b")), millis(0));
+    assert_eq!(recorder.at(&end("b")), millis(100));
+    assert_eq!(recorder.at(&end("a")), millis(300));
+    // The next batch waits for the slowest step of the batch.
+    assert_eq!(recorder.at(&start("c")), millis(300));
+}
+
+#[tokio::test(start_paused = true)]
+async fn limits_the_steps_running_at_once() {
+    let properties = ExecutorProperties::new()
+        .with_jobs(1)
+        .then_run_parallel(vec![
+            FakeStep::succeed("a").sleep(100).step(),
+            FakeStep::succeed("b").sleep(100).step(),
+        ]);
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    let mut ends = vec![recorder.at(&end("a")), recorder.at(&end("b"))];
+    ends.sort();
+    assert_eq!(ends, vec![millis(100), millis(200)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn reports_the_progress_of_nested_sequences() {
+    let nested = StepSequence::new("nested", "")
+        .then_run(FakeStep::succeed("c").sleep(100).step())
+        .then_run(FakeStep::succeed("d").step());
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").step())
+        .then_run_parallel(vec![FakeStep::succeed("b").step(), Step::Sequence(nested)]);
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    let events = recorder.lifecycle();
+    // Steps of the nested sequence report to it, not to the sequence it is part of.
+    assert_eq!(events[0], Recorded::SequenceStart("main".to_string(), 2));
+    assert!(events.contains(&Recorded::SequenceStart("nested".to_string(), 2)));
+    let position = |event: &Recorded| events.iter().position(|e| e == event).unwrap();
+    assert!(position(&end("d")) < position(&Recorded::SequenceEnd("nested".to_string())));
+    assert_eq!(
+        events.last(),
+        Some(&Recorded::SequenceEnd("main".to_string()))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn skips_completed_steps() {
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::skip("a").step())
+        .then_run(FakeStep::succeed("b").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    let events = recorder.lifecycle();
+    assert!(events.contains(&Recorded::Skip("a".to_string())));
+    assert!(!events.contains(&end("a")));
+    assert!(events.contains(&end("b")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn skips_every_step_of_a_sequence_whose_condition_is_unmet() {
+    let unmet = Condition::new("the sequence is wanted", |_ctx| async { Ok(false) });
+    let sequence = StepSequence::new("sequence", "")
+        .then_run(FakeStep::succeed("a").step())
+        .then_run(FakeStep::succeed("b").step());
+    let properties = ExecutorProperties::new()
+        .then_run_if(Step::Sequence(sequence), unmet)
+        .then_run(FakeStep::succeed("c").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    let events = recorder.lifecycle();
+    assert!(events.contains(&Recorded::Skip("a".to_string())));
+    assert!(events.contains(&Recorded::Skip("b".to_string())));
+    assert!(events.contains(&end("c")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn stops_the_sequence_at_a_failed_step() {
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::fail("a").step())
+        .then_run(FakeStep::succeed("b").step());
+    let (result, recorder) = run(properties).await;
+
+    let report = result.unwrap_err();
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].title, "a");
+    assert!(matches!(
+        report.failures[0].error,
+        OecliError::Precondition(_)
+    ));
+    let events = recorder.lifecycle();
+    assert!(events.contains(&Recorded::Error("a".to_string())));
+    assert!(!events.contains(&start("b")));
+    assert!(!events.contains(&Recorded::SequenceEnd("main".to_string())));
+}
+
+#[tokio::test(start_paused = true)]
+async fn fail_fast_interrupts_the_rest_of_the_batch() {
+    let properties = ExecutorProperties::new().then_run_parallel(vec![
+        FakeStep::fail("a").sleep(100).step(),
+        FakeStep::succeed("b").sleep(1000).step(),
+    ]);
+    let (result, recorder) = run(properties).await;
+
+    let report = result.unwrap_err();
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].title, "a");
+    assert_eq!(OecliError::from(report).exit_code(), 6);
+    assert_eq!(
+        recorder.at(&Recorded::Interrupt("b".to_string())),
+        millis(100)
+    );
+    assert!(!recorder.lifecycle().contains(&end("b")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelling_the_run_reports_the_steps_in_progress() {
+    let properties = ExecutorProperties::new().then_run_parallel(vec![
+        FakeStep::succeed("a").sleep(1000).step(),
+        FakeStep::succeed("b").sleep(1000).step(),
+    ]);
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new());
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        executor.cancel();
+    };
+
+    let (result, _) = tokio::join!(executor.run(), cancel);
+
+    let report = result.unwrap_err();
+    assert_eq!(report.failures.len(), 2);
+    assert!(report
+        .failures
+        .iter()
+        .all(|failure| matches!(failure.error, OecliError::Cancelled)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn continue_lets_the_rest_of_the_batch_finish() {
+    let properties = ExecutorProperties::new()
+        .with_failure_mode(FailureMode::Continue)
+        .then_run_parallel(vec![
+            FakeStep::fail("a").sleep(100).step(),
+            FakeStep::succeed("b").sleep(1000).step(),
+        ])
+        .then_run(FakeStep::succeed("c").step());
+    let (result, recorder) = run(properties).await;
+
+    assert_eq!(result.unwrap_err().failures.len(), 1);
+    assert_eq!(recorder.at(&end("b")), millis(1000));
+    // The batch after the failure never runs.
+    assert!(!recorder.lifecycle().contains(&start("c")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn finish_in_flight_lets_running_steps_finish_but_starts_no_more() {
+    let mut graph = StepGraph::new("graph", "").with_failure_mode(FailureMode::FinishInFlight);
+    graph.add(Box::new(FakeStep::fail("a").sleep(100)), &[]);
+    let b = graph.add(Box::new(FakeStep::succeed("b").sleep(1000)), &[]);
+    graph.add(Box::new(FakeStep::succeed("c")), &[b]);
+    let properties = ExecutorProperties::new().then_run(Step::Graph(graph));
+    let (result, recorder) = run(properties).await;
+
+    assert_eq!(result.unwrap_err().failures.len(), 1);
+    assert_eq!(recorder.at(&end("b")), millis(1000));
+    assert!(!recorder.lifecycle().contains(&start("c")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn rolls_back_completed_steps_in_reverse_order() {
+    let properties = ExecutorProperties::new()
+        .with_rollback_on_failure()
+        .then_run(FakeStep::succeed("a").undoable().step())
+        .then_run(FakeStep::succeed("b").step())
+        .then_run(FakeStep::succeed("c").undoable().step())
+        .then_run(FakeStep::fail("d").undoable().step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_err());
+    let rolled_back: Vec<Recorded> = recorder
+        .lifecycle()
+        .into_iter()
+        .filter(|recorded| matches!(recorded, Recorded::RolledBack(_)))
+        .collect();
+    // Steps without an undo action and the failed step are left as they are.
+    assert_eq!(
+        rolled_back,
+        [
+            Recorded::RolledBack("c".to_string()),
+            Recorded::RolledBack("a".to_string())
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_rolls_back_when_asked_to() {
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").undoable().step())
+        .then_run(FakeStep::fail("b").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_err());
+    assert!(!recorder
+        .lifecycle()
+        .contains(&Recorded::RolledBack("a".to_string())));
+}
+
+#[tokio::test(start_paused = true)]
+async fn starts_graph_steps_once_their_dependencies_completed() {
+    let mut graph = StepGraph::new("graph", "");
+    let a = graph.add(Box::new(FakeStep::succeed("a").sleep(100)), &[]);
+    let b = graph.add(Box::new(FakeStep::succeed("b").sleep(300)), &[]);
+    graph.add(Box::new(FakeStep::succeed("c")), &[a]);
+    graph.add(Box::new(FakeStep::succeed("d")), &[a, b]);
+    let properties = ExecutorProperties::new().then_run(Step::Graph(graph));
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    assert_eq!(recorder.at(&start("c")), millis(100));
+    assert_eq!(recorder.at(&start("d")), millis(300));
+}
+
+#[tokio::test(start_paused = true)]
+async fn runs_graph_steps_that_share_a_title() {
+    let mut graph = StepGraph::new("graph", "");
+    let a = graph.add(Box::new(FakeStep::succeed("a").sleep(100)), &[]);
+    graph.add(Box::new(FakeStep::succeed("a").sleep(100)), &[a]);
+    let properties = ExecutorProperties::new().then_run(Step::Graph(graph));
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    let starts: Vec<Duration> = recorder
+        .timed()
+        .into_iter()
+        .filter(|(_, recorded)| *recorded == start("a"))
+        .map(|(at, _)| at)
+        .collect();
+    assert_eq!(starts, [millis(0).unwrap(), millis(100).unwrap()]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn interrupts_steps_that_time_out() {
+    let properties = ExecutorProperties::new()
+        .with_step_timeout(Duration::from_millis(500))
+        .then_run(FakeStep::succeed("a").sleep(1000).step());
+    let (result, recorder) = run(properties).await;
+
+    let report = result.unwrap_err();
+    assert!(matches!(report.failures[0].error, OecliError::Timeout(_)));
+    assert_eq!(
+        recorder.at(&Recorded::Interrupt("a".to_string())),
+        millis(500)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_failed_attempts() {
+    let step = FakeStep::succeed("a").fail_first(
+        2,
+        RetryPolicy::new(3, Backoff::Fixed(Duration::from_millis(100))),
+    );
+    let attempts = step.attempts();
+    let properties = ExecutorProperties::new().then_run(step.step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(result.is_ok());
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    let events = recorder.lifecycle();
+    assert!(events.contains(&Recorded::Retry("a".to_string(), 1)));
+    assert!(events.contains(&Recorded::Retry("a".to_string(), 2)));
+    assert_eq!(recorder.at(&end("a")), millis(200));
+}
+
+#[tokio::test(start_paused = true)]
+async fn fails_the_step_that_panics() {
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::panic("a").step())
+        .then_run(FakeStep::succeed("b").step());
+    let (result, recorder) = run(properties).await;
+
+    let report = result.unwrap_err();
+    assert_eq!(report.failures[0].title, "a");
+    match &report.failures[0].error {
+        OecliError::Panic { msg, location } => {
+            assert_eq!(msg, "a panicked.");
+            assert!(location
+                .as_deref()
+                .unwrap()
+                .starts_with("src/step/testing.rs"));
+        }
+        error => panic!("Expected a panic, got {:?}.", error),
+    }
+    let events = recorder.lifecycle();
+    assert!(events.contains(&Recorded::Error("a".to_string())));
+    assert!(!events.contains(&start("b")));
+}
+
+#[tokio::test(start_paused = true)]
+async fn fails_the_step_that_panics_in_should_run() {
+    let properties = ExecutorProperties::new().then_run(FakeStep::panic_on_check("a").step());
+    let (result, recorder) = run(properties).await;
+
+    assert!(matches!(
+        result.unwrap_err().failures[0].error,
+        OecliError::Panic { .. }
+    ));
+    assert!(recorder
+        .lifecycle()
+        .contains(&Recorded::Error("a".to_string())));
+}
+
+#[tokio::test(start_paused = true)]
+async fn continue_lets_the_rest_of_the_batch_finish_after_a_panic() {
+    let properties = ExecutorProperties::new()
+        .with_failure_mode(FailureMode::Continue)
+        .then_run_parallel(vec![
+            FakeStep::panic("a").step(),
+            FakeStep::succeed("b").sleep(100).step(),
+        ]);
+    let (result, recorder) = run(properties).await;
+
+    assert_eq!(result.unwrap_err().failures.len(), 1);
+    assert_eq!(recorder.at(&end("b")), millis(100));
+}
+
+#[tokio::test]
+async fn plans_every_step_without_executing_it() {
+    let a = FakeStep::succeed("a");
+    let attempts = a.attempts();
+    let unmet = Condition::new("c is wanted", |_ctx| async { Ok(false) });
+    let broken = Condition::new("d can be checked", |_ctx| async {
+        Err(OecliError::Precondition(
+            "d can not be checked.".to_string(),
+        ))
+    });
+    let mut graph = StepGraph::new("graph", "");
+    let e = graph.add(Box::new(FakeStep::succeed("e")), &[]);
+    graph.add(Box::new(FakeStep::succeed("f")), &[e]);
+    let properties = ExecutorProperties::new()
+        .then_run(a.step())
+        .then_run_parallel(vec![
+            FakeStep::skip("b").step(),
+            Step::Step(unmet.guard(Box::new(FakeStep::succeed("c")))),
+        ])
+        .then_run(Step::Step(broken.guard(Box::new(FakeStep::succeed("d")))))
+        .then_run(Step::Graph(graph));
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new());
+
+    let plan = executor.plan().await;
+
+    assert_eq!(plan.totals(), (3, 2, 1, 0));
+    assert_eq!(
+        plan.to_string(),
+        "Sequence: main
+  [run]   a
+  Parallel:
+    [skip]  b (Already completed.)
+    [skip]  c (Only runs if c is wanted.)
+  [error] d: d can not be checked.
+  Graph: graph
+    [run]   e
+    [run]   f
+      after: e
+
+3 step(s) would run, 2 would be skipped, 1 would error."
+    );
+    assert_eq!(attempts.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn plans_steps_that_need_values_of_earlier_steps_as_pending() {
+    const OWNER: ContextKey<String> = ContextKey::new("owner");
+    let owned = Condition::new("the owner is oe", |ctx| async move {
+        Ok(ctx.require(&OWNER)? == "oe")
+    });
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").step())
+        .then_run(Step::Step(owned.guard(Box::new(FakeStep::succeed("b")))));
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new());
+
+    let plan = executor.plan().await;
+
+    assert_eq!(plan.totals(), (1, 0, 0, 1));
+    assert_eq!(
+        plan.to_string(),
+        "Sequence: main
+  [run]   a
+  [later] b (Depends on values produced at run time. owner has not been set by an earlier step.)
+
+1 step(s) would run, 0 would be skipped, 0 would error. 1 depend on values produced at run time."
+    );
+}
+
+#[tokio::test]
+async fn plans_an_invalid_graph_as_an_error() {
+    let mut graph = StepGraph::new("graph", "");
+    graph.add(Box::new(FakeStep::succeed("a")), &[StepId::new_random()]);
+    let properties = ExecutorProperties::new().then_run(Step::Graph(graph));
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new());
+
+    let plan = executor.plan().await;
+
+    assert_eq!(plan.totals(), (0, 0, 1, 0));
+    assert!(plan
+        .to_string()
+        .contains("[error] graph: Step a depends on "));
+}
+
+#[tokio::test(start_paused = true)]
+async fn follows_the_slowest_steps_along_the_critical_path() {
+    let mut graph = StepGraph::new("graph", "");
+    let d = graph.add(Box::new(FakeStep::succeed("d").sleep(200)), &[]);
+    let e = graph.add(Box::new(FakeStep::succeed("e").sleep(100)), &[]);
+    graph.add(Box::new(FakeStep::succeed("f").sleep(100)), &[d, e]);
+    let properties = ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").sleep(100).step())
+        .then_run_parallel(vec![
+            FakeStep::succeed("b").sleep(300).step(),
+            FakeStep::succeed("c").sleep(100).step(),
+        ])
+        .then_run(Step::Graph(graph));
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new());
+    executor.run().await.unwrap();
+
+    let timings = executor.timings().await.to_string();
+
+    assert_eq!(
+        timings,
+        "Step               Start  Duration  Status
+Sequence: main      0.0s      0.7s
+  a                 0.0s      0.1s  Completed
+  Parallel:         0.1s      0.3s
+    b               0.1s      0.3s  Completed
+    c               0.1s      0.1s  Completed
+  Graph: graph      0.4s      0.3s
+    d               0.4s      0.2s  Completed
+    e               0.4s      0.1s  Completed
+    f               0.6s      0.1s  Completed
+
+Critical path (0.7s):
+  a (0.1s)
+  b (0.3s)
+  d (0.2s)
+  f (0.1s)
+
+Waiting at join points:
+  main: 0.2s waiting for b
+"
+    );
+}
+
+/// A step, followed by a graph, followed by two steps in parallel.
+fn chain() -> ExecutorProperties {
+    let mut graph = StepGraph::new("graph", "");
+    let b = graph.add(Box::new(FakeStep::succeed("b")), &[]);
+    graph.add(Box::new(FakeStep::succeed("c")), &[b]);
+    graph.add(Box::new(FakeStep::succeed("d \"quoted\"")), &[]);
+    ExecutorProperties::new()
+        .then_run(FakeStep::succeed("a").step())
+        .then_run(Step::Graph(graph))
+        .then_run_parallel(vec![
+            FakeStep::succeed("e").step(),
+            FakeStep::succeed("f").step(),
+        ])
+}
+
+#[tokio::test]
+async fn draws_a_mermaid_diagram_of_the_steps() {
+    let executor = StepExecutor::from_executor_properties(chain(), RecordingEventHandler::new());
+
+    let diagram = executor.diagram().await.render(DiagramFormat::Mermaid);
+
+    assert_eq!(
+        diagram,
+        r#"flowchart TD
+  subgraph c0["main"]
+    s0["a"]
+    subgraph c1["graph"]
+      s1["b"]
+      s2["c"]
+      s3["d #quot;quoted#quot;"]
+    end
+    s4["e"]
+    s5["f"]
+  end
+  s1 --> s2
+  s0 --> s1
+  s0 --> s3
+  s2 --> s4
+  s2 --> s5
+  s3 --> s4
+  s3 --> s5"#
+    );
+}
+
+#[tokio::test]
+async fn draws_a_graphviz_diagram_of_the_steps() {
+    let executor = StepExecutor::from_executor_properties(chain(), RecordingEventHandler::new());
+
+    let diagram = executor.diagram().await.render(DiagramFormat::Dot);
+
+    assert_eq!(
+        diagram,
+        r#"digraph steps {
+  rankdir=TB;
+  node [shape=box];
+  subgraph cluster_0 {
+    label="main";
+    s0 [label="a"];
+    subgraph cluster_1 {
+      label="graph";
+      s1 [label="b"];
+      s2 [label="c"];
+      s3 [label="d \"quoted\""];
+    }
+    s4 [label="e"];
+    s5 [label="f"];
+  }
+  s1 -> s2;
+  s0 -> s1;
+  s0 -> s3;
+  s2 -> s4;
+  s2 -> s5;
+  s3 -> s4;
+  s3 -> s5;
+}"#
+    );
+}
+
+/// A step that completes, one guarded by a condition that holds once `met` is set, and the last
+/// step. Returns how often the first two were executed.
+fn resumable(
+    met: &Arc<AtomicBool>,
+    last: FakeStep,
+) -> (ExecutorProperties, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let a = FakeStep::succeed("a");
+    let b = FakeStep::succeed("b");
+    let (a_attempts, b_attempts) = (a.attempts(), b.attempts());
+    let met = met.clone();
+    let condition = Condition::new("b is wanted", move |_ctx| {
+        let met = met.load(Ordering::SeqCst);
+        async move { Ok(met) }
+    });
+    let properties = ExecutorProperties::new()
+        .then_run(a.step())
+        .then_run(Step::Step(condition.guard(Box::new(b))))
+        .then_run(last.step());
+    (properties, a_attempts, b_attempts)
+}
+
+#[tokio::test]
+async fn resumes_after_completed_steps_and_checks_unmet_conditions_again() {
+    let dir = TempDir::new();
+    let met = Arc::new(AtomicBool::new(false));
+    let (properties, _, b_attempts) = resumable(&met, FakeStep::fail("c"));
+    let journal = RunJournal::new_in(Path::new(dir.path())).await.unwrap();
+    let run_id = journal.run_id().to_string();
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new())
+        .with_journal(journal);
+    assert!(executor.run().await.is_err());
+    assert_eq!(b_attempts.load(Ordering::SeqCst), 0);
+
+    met.store(true, Ordering::SeqCst);
+    let (properties, a_attempts, b_attempts) = resumable(&met, FakeStep::succeed("c"));
+    let journal = RunJournal::resume_from(Path::new(dir.path()), &run_id)
+        .await
+        .unwrap();
+    let recorder = RecordingEventHandler::new();
+    let executor =
+        StepExecutor::from_executor_properties(properties, recorder.clone()).with_journal(journal);
+    assert!(executor.run().await.is_ok());
+
+    assert_eq!(a_attempts.load(Ordering::SeqCst), 0);
+    assert_eq!(b_attempts.load(Ordering::SeqCst), 1);
+    assert!(recorder
+        .lifecycle()
+        .contains(&Recorded::Skip("a".to_string())));
+    assert!(recorder.lifecycle().contains(&end("c")));
+}
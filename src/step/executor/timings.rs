@@ -0,0 +1,383 @@
+//! Timings describe how long every step and sequence of a finished run took. They point out the
+//! critical path, the chain of steps that decided how long the run took, and how long steps
+//! running alongside each other waited for the slowest of them.
+
+use super::StepNode;
+use crate::error::OecliError;
+use crate::step::{StepId, StepState, StepStatus, Steps};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// When a step, or the first step of a group, started and when the last one finished.
+#[derive(Clone, Copy)]
+struct Span {
+    start: Instant,
+    end: Instant,
+}
+
+impl Span {
+    fn of(state: &StepState) -> Option<Span> {
+        Some(Span {
+            start: state.started?,
+            end: state.finished?,
+        })
+    }
+
+    /// The span of the node, `None` when none of its steps ran.
+    fn of_node(node: &StepNode, steps: &Steps) -> Option<Span> {
+        match node {
+            StepNode::Item(id) => steps.get(id).and_then(Span::of),
+            StepNode::Sequence { batches, .. } => Span::cover(
+                batches
+                    .iter()
+                    .flatten()
+                    .filter_map(|node| Span::of_node(node, steps)),
+            ),
+            StepNode::Graph { nodes, .. } => Span::cover(
+                nodes
+                    .iter()
+                    .filter_map(|(id, _)| steps.get(id).and_then(Span::of)),
+            ),
+            StepNode::Parallel(nodes) => {
+                Span::cover(nodes.iter().filter_map(|node| Span::of_node(node, steps)))
+            }
+        }
+    }
+
+    fn cover(spans: impl Iterator<Item = Span>) -> Option<Span> {
+        spans.reduce(|a, b| Span {
+            start: a.start.min(b.start),
+            end: a.end.max(b.end),
+        })
+    }
+
+    fn duration(&self) -> Duration {
+        self.end.saturating_duration_since(self.start)
+    }
+}
+
+/// A line of the timings table.
+struct TimingRow {
+    depth: usize,
+    title: String,
+    /// Sequences, graphs and parallel steps group other rows.
+    group: bool,
+    span: Option<Span>,
+    status: Option<StepStatus>,
+}
+
+/// A point where steps running alongside each other are joined before the sequence moves on.
+struct JoinWait {
+    sequence: String,
+    /// The step or group that finished last.
+    slowest: String,
+    /// How long the first to finish waited for the last.
+    waited: Duration,
+}
+
+/// How long every step and sequence of a run took. Displayed as a table, followed by the critical
+/// path and the time spent waiting at join points.
+pub struct Timings {
+    /// When the first step started, the table lists every start relative to it.
+    origin: Option<Instant>,
+    rows: Vec<TimingRow>,
+    critical_path: Vec<(String, Duration)>,
+    joins: Vec<JoinWait>,
+}
+
+/// A complete event of the Chrome trace event format, times are in microseconds.
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'a str,
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    tid: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<TraceArgs>,
+}
+
+#[derive(Serialize)]
+struct TraceArgs {
+    status: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: Vec<TraceEvent<'a>>,
+    display_time_unit: &'a str,
+}
+
+impl Timings {
+    /// Collects the timings of every step in the node. Steps that never started are listed without
+    /// times.
+    pub(super) fn build(root: &StepNode, steps: &Steps) -> Timings {
+        let mut timings = Timings {
+            origin: Span::of_node(root, steps).map(|span| span.start),
+            rows: Vec::new(),
+            critical_path: Vec::new(),
+            joins: Vec::new(),
+        };
+        timings.add(root, steps, 0, "");
+        timings.critical_path = critical_path(root, steps)
+            .iter()
+            .filter_map(|id| steps.get(id))
+            .filter_map(|state| Some((state.details.title.clone(), Span::of(state)?.duration())))
+            .collect();
+        timings
+    }
+
+    fn add(&mut self, node: &StepNode, steps: &Steps, depth: usize, sequence_name: &str) {
+        let span = Span::of_node(node, steps);
+        match node {
+            StepNode::Item(id) => {
+                let state = steps.get(id);
+                self.rows.push(TimingRow {
+                    depth,
+                    title: title(node, steps),
+                    group: false,
+                    span,
+                    status: state.map(|state| state.status),
+                });
+            }
+            StepNode::Sequence { title, batches, .. } => {
+                self.push_group(depth, format!("Sequence: {}", title), span);
+                for batch in batches {
+                    self.add_batch(batch, steps, depth + 1, title);
+                }
+            }
+            StepNode::Graph { title, nodes, .. } => {
+                self.push_group(depth, format!("Graph: {}", title), span);
+                for (id, _) in nodes {
+                    self.add(&StepNode::Item(*id), steps, depth + 1, title);
+                }
+            }
+            StepNode::Parallel(nodes) => self.add_batch(nodes, steps, depth, sequence_name),
+        }
+    }
+
+    /// Batches with more than one node are grouped under a parallel heading, and are a join
+    /// point.
+    fn add_batch(&mut self, batch: &[StepNode], steps: &Steps, depth: usize, sequence_name: &str) {
+        if let [node] = batch {
+            return self.add(node, steps, depth, sequence_name);
+        }
+        let spans: Vec<_> = batch
+            .iter()
+            .filter_map(|node| Some((node, Span::of_node(node, steps)?)))
+            .collect();
+        self.push_group(
+            depth,
+            "Parallel:".to_string(),
+            Span::cover(spans.iter().map(|(_, span)| *span)),
+        );
+        for node in batch {
+            self.add(node, steps, depth + 1, sequence_name);
+        }
+        let first = spans.iter().map(|(_, span)| span.end).min();
+        let last = spans.iter().max_by_key(|(_, span)| span.end);
+        if let (Some(first), Some((slowest, span))) = (first, last) {
+            if spans.len() > 1 {
+                self.joins.push(JoinWait {
+                    sequence: sequence_name.to_string(),
+                    slowest: title(slowest, steps),
+                    waited: span.end.saturating_duration_since(first),
+                });
+            }
+        }
+    }
+
+    fn push_group(&mut self, depth: usize, title: String, span: Option<Span>) {
+        self.rows.push(TimingRow {
+            depth,
+            title,
+            group: true,
+            span,
+            status: None,
+        });
+    }
+
+    /// Writes the timings in the Chrome trace event format, which `chrome://tracing` and Perfetto
+    /// can display. Sequences are drawn on the first row, steps that overlap on rows below it.
+    pub fn write_trace(&self, path: &str) -> Result<(), OecliError> {
+        let origin = self.origin.unwrap_or_else(Instant::now);
+        // The end of the last step drawn on each row.
+        let mut lanes: Vec<Instant> = Vec::new();
+        let mut rows: Vec<_> = self
+            .rows
+            .iter()
+            .filter_map(|row| Some((row, row.span?)))
+            .collect();
+        rows.sort_by_key(|(_, span)| span.start);
+        let trace_events = rows
+            .into_iter()
+            .map(|(row, span)| {
+                let tid = if row.group {
+                    0
+                } else {
+                    match lanes.iter().position(|end| *end <= span.start) {
+                        Some(lane) => {
+                            lanes[lane] = span.end;
+                            lane + 1
+                        }
+                        None => {
+                            lanes.push(span.end);
+                            lanes.len()
+                        }
+                    }
+                };
+                TraceEvent {
+                    name: &row.title,
+                    cat: if row.group { "sequence" } else { "step" },
+                    ph: "X",
+                    ts: span.start.saturating_duration_since(origin).as_micros(),
+                    dur: span.duration().as_micros(),
+                    pid: 1,
+                    tid,
+                    args: row.status.map(|status| TraceArgs {
+                        status: status.to_string(),
+                    }),
+                }
+            })
+            .collect();
+        let trace = Trace {
+            trace_events,
+            display_time_unit: "ms",
+        };
+        let json = serde_json::to_string(&trace)
+            .map_err(|e| OecliError::Internal(format!("Unable to serialize the trace. {}", e)))?;
+        std::fs::write(path, json)
+            .map_err(|e| OecliError::io(&format!("Unable to write {}.", path), e))
+    }
+}
+
+/// Title of the node as it appears in the table.
+fn title(node: &StepNode, steps: &Steps) -> String {
+    match node {
+        StepNode::Item(id) => steps
+            .get(id)
+            .map(|state| state.details.title.clone())
+            .unwrap_or_else(|| id.to_string()),
+        StepNode::Sequence { title, .. } | StepNode::Graph { title, .. } => title.clone(),
+        StepNode::Parallel(_) => "Parallel steps".to_string(),
+    }
+}
+
+/// The steps that decided how long the node took. Within a batch, that is the node that finished
+/// last. Within a graph, it is the step that finished last, preceded by the dependency that
+/// finished last before it.
+fn critical_path(node: &StepNode, steps: &Steps) -> Vec<StepId> {
+    match node {
+        StepNode::Item(id) => match steps.get(id).and_then(Span::of) {
+            Some(_) => vec![*id],
+            None => Vec::new(),
+        },
+        StepNode::Sequence { batches, .. } => batches
+            .iter()
+            .flat_map(|batch| critical_batch(batch, steps))
+            .collect(),
+        StepNode::Parallel(nodes) => critical_batch(nodes, steps),
+        StepNode::Graph { nodes, .. } => {
+            let last = |ids: &mut dyn Iterator<Item = &StepId>| {
+                ids.filter_map(|id| Some((*id, steps.get(id).and_then(Span::of)?.end)))
+                    .max_by_key(|(_, end)| *end)
+                    .map(|(id, _)| id)
+            };
+            let mut path = Vec::new();
+            let mut next = last(&mut nodes.iter().map(|(id, _)| id));
+            while let Some(id) = next {
+                path.push(id);
+                next = nodes
+                    .iter()
+                    .find(|(node, _)| *node == id)
+                    .and_then(|(_, dependencies)| last(&mut dependencies.iter()));
+            }
+            path.reverse();
+            path
+        }
+    }
+}
+
+fn critical_batch(batch: &[StepNode], steps: &Steps) -> Vec<StepId> {
+    batch
+        .iter()
+        .filter_map(|node| Some((node, Span::of_node(node, steps)?.end)))
+        .max_by_key(|(_, end)| *end)
+        .map(|(node, _)| critical_path(node, steps))
+        .unwrap_or_default()
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.depth * 2 + row.title.len())
+            .max()
+            .unwrap_or_default()
+            .max("Step".len());
+        writeln!(
+            f,
+            "{:<width$}  {:>8}  {:>8}  Status",
+            "Step",
+            "Start",
+            "Duration",
+            width = width
+        )?;
+        for row in &self.rows {
+            let title = format!("{}{}", "  ".repeat(row.depth), row.title);
+            let (start, duration) = match (row.span, self.origin) {
+                (Some(span), Some(origin)) => (
+                    format!(
+                        "{:.1}s",
+                        span.start.saturating_duration_since(origin).as_secs_f32()
+                    ),
+                    format!("{:.1}s", span.duration().as_secs_f32()),
+                ),
+                _ => ("-".to_string(), "-".to_string()),
+            };
+            let status = match (row.group, row.status) {
+                (false, Some(status)) => status.to_string(),
+                _ => String::new(),
+            };
+            let line = format!(
+                "{:<width$}  {:>8}  {:>8}  {}",
+                title,
+                start,
+                duration,
+                status,
+                width = width
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        if !self.critical_path.is_empty() {
+            let total: Duration = self
+                .critical_path
+                .iter()
+                .map(|(_, duration)| *duration)
+                .sum();
+            writeln!(f, "\nCritical path ({:.1}s):", total.as_secs_f32())?;
+            for (title, duration) in &self.critical_path {
+                writeln!(f, "  {} ({:.1}s)", title, duration.as_secs_f32())?;
+            }
+        }
+        if !self.joins.is_empty() {
+            writeln!(f, "\nWaiting at join points:")?;
+            for join in &self.joins {
+                writeln!(
+                    f,
+                    "  {}: {:.1}s waiting for {}",
+                    join.sequence,
+                    join.waited.as_secs_f32(),
+                    join.slowest
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! A graph of steps where every step declares the steps it depends on.
+
+use crate::step::{BoxedStepItem, FailureMode, StepId};
+
+/// A set of steps with dependencies between them. The executor starts every step as soon as all of
+/// its dependencies have completed, so independent steps run at the same time.
+///
+/// Example:
+///
+/// ```
+/// let mut graph = StepGraph::new("Unique Graph Name", "Detailed Description");
+/// let create = graph.add(Box::new(step1), &[]);
+/// let clone = graph.add(Box::new(step2), &[create]);
+/// graph.add(Box::new(step3), &[clone]);
+/// graph.add(Box::new(step4), &[clone]);
+/// ```
+pub struct StepGraph {
+    /// A description of what is expected to happen within this graph.
+    #[allow(dead_code)]
+    pub description: String,
+    /// How the graph reacts to a failed step. Inherited from the enclosing sequence when not set.
+    pub failure_mode: Option<FailureMode>,
+    /// Steps in the order they were added.
+    pub steps: Vec<GraphStep>,
+    /// A unique name for the graph.
+    pub title: String,
+}
+
+/// A step within a [StepGraph] and the ids of the steps it depends on.
+pub struct GraphStep {
+    pub dependencies: Vec<StepId>,
+    pub id: StepId,
+    pub step: BoxedStepItem,
+}
+
+impl StepGraph {
+    pub fn new(title: &str, description: &str) -> StepGraph {
+        StepGraph {
+            description: description.to_owned(),
+            failure_mode: None,
+            steps: Vec::new(),
+            title: title.to_owned(),
+        }
+    }
+
+    /// Sets how the graph reacts when one of its steps fails. Steps that depend on a failed step
+    /// never run.
+    pub fn with_failure_mode(mut self, failure_mode: FailureMode) -> StepGraph {
+        self.failure_mode = Some(failure_mode);
+        self
+    }
+
+    /// Adds a step that starts once every dependency has completed. Returns the id other steps of
+    /// this graph can depend on. The executor registers the step under an id made up of its
+    /// position within the graph, so it stays the same between runs.
+    pub fn add(&mut self, step: BoxedStepItem, dependencies: &[StepId]) -> StepId {
+        let id = StepId::new_random();
+        self.steps.push(GraphStep {
+            dependencies: dependencies.to_vec(),
+            id,
+            step,
+        });
+        id
+    }
+}
@@ -0,0 +1,213 @@
+//! The run journal records the outcome of every step so a failed run can be resumed. Steps that
+//! completed in a previous run, or that `should_run` found already done, are skipped without
+//! calling `should_run` again. Steps skipped because a condition was unmet are not recorded as
+//! skipped, the condition is checked again when the run is resumed.
+//!
+//! Journals are stored as json under `.oecli/runs/<run-id>.json` in the current directory.
+
+use crate::error::OecliError;
+use crate::step::{StepDetails, StepId, StepStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Directory the journals are written to, relative to the current directory.
+const RUNS_DIR: &str = ".oecli/runs";
+
+/// Outcome of a single step within a run.
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub title: String,
+    pub status: StepStatus,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    run_id: String,
+    steps: BTreeMap<StepId, JournalEntry>,
+    /// Values of the [RunContext](crate::step::RunContext) when the journal was last written.
+    #[serde(default)]
+    context: BTreeMap<String, serde_json::Value>,
+}
+
+/// A persisted record of every step's outcome for a single run.
+pub struct RunJournal {
+    path: PathBuf,
+    record: JournalRecord,
+}
+
+impl RunJournal {
+    /// Starts the journal for a new run with a random run id.
+    pub async fn new() -> Result<RunJournal, OecliError> {
+        RunJournal::new_in(Path::new(RUNS_DIR)).await
+    }
+
+    /// Loads the journal of a previous run to resume it.
+    pub async fn resume(run_id: &str) -> Result<RunJournal, OecliError> {
+        RunJournal::resume_from(Path::new(RUNS_DIR), run_id).await
+    }
+
+    /// Starts the journal for a new run within the directory.
+    pub async fn new_in(dir: &Path) -> Result<RunJournal, OecliError> {
+        let run_id = uuid::Uuid::new_v4().to_string();
+        let journal = RunJournal {
+            path: RunJournal::journal_path(dir, &run_id),
+            record: JournalRecord {
+                run_id,
+                steps: BTreeMap::new(),
+                context: BTreeMap::new(),
+            },
+        };
+        journal.save().await?;
+        Ok(journal)
+    }
+
+    /// Loads the journal of a previous run from the directory. The run id must be the uuid a
+    /// previous run printed, so it cannot name a file outside the directory.
+    pub async fn resume_from(dir: &Path, run_id: &str) -> Result<RunJournal, OecliError> {
+        if uuid::Uuid::parse_str(run_id).is_err() {
+            return Err(OecliError::Precondition(format!(
+                "{} is not the id of a run.",
+                run_id
+            )));
+        }
+        let path = RunJournal::journal_path(dir, run_id);
+        let contents = tokio::fs::read_to_string(&path).await.map_err(|e| {
+            OecliError::io(
+                &format!(
+                    "Unable to read the journal for run {} at {}.",
+                    run_id,
+                    path.display()
+                ),
+                e,
+            )
+        })?;
+        let record = serde_json::from_str(&contents).map_err(|e| {
+            OecliError::io(
+                &format!("Journal for run {} is not valid.", run_id),
+                e.into(),
+            )
+        })?;
+        Ok(RunJournal { path, record })
+    }
+
+    fn journal_path(dir: &Path, run_id: &str) -> PathBuf {
+        dir.join(format!("{}.json", run_id))
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.record.run_id
+    }
+
+    /// Status of the step when the journal was last written.
+    pub fn status(&self, id: &StepId) -> Option<StepStatus> {
+        self.record.steps.get(id).map(|entry| entry.status)
+    }
+
+    /// Values of the run context the previous run left behind.
+    pub fn context(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.record.context
+    }
+
+    /// Records the status of the step and the values of the run context, then writes the journal
+    /// to disk.
+    pub async fn record(
+        &mut self,
+        details: &StepDetails,
+        status: StepStatus,
+        context: BTreeMap<String, serde_json::Value>,
+    ) -> Result<(), OecliError> {
+        self.record.context = context;
+        self.record.steps.insert(
+            details.id,
+            JournalEntry {
+                title: details.title.clone(),
+                status,
+            },
+        );
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<(), OecliError> {
+        let write = async {
+            if let Some(dir) = self.path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let contents = serde_json::to_string_pretty(&self.record)?;
+            tokio::fs::write(&self.path, contents).await
+        };
+        write.await.map_err(|e| {
+            OecliError::io(
+                &format!(
+                    "Unable to write the run journal to {}.",
+                    self.path.display()
+                ),
+                e,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::TempDir;
+
+    fn details(title: &str) -> StepDetails {
+        StepDetails {
+            id: StepId::from_key(title),
+            title: title.to_string(),
+            description: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_the_recorded_steps_and_context() {
+        let dir = TempDir::new();
+        let mut journal = RunJournal::new_in(Path::new(dir.path())).await.unwrap();
+        let context = BTreeMap::from([("owner".to_string(), serde_json::json!("oe"))]);
+        journal
+            .record(&details("a"), StepStatus::Completed, context.clone())
+            .await
+            .unwrap();
+        journal
+            .record(&details("b"), StepStatus::Error, context.clone())
+            .await
+            .unwrap();
+
+        let resumed = RunJournal::resume_from(Path::new(dir.path()), journal.run_id())
+            .await
+            .unwrap();
+
+        assert_eq!(resumed.run_id(), journal.run_id());
+        assert_eq!(
+            resumed.status(&details("a").id),
+            Some(StepStatus::Completed)
+        );
+        assert_eq!(resumed.status(&details("b").id), Some(StepStatus::Error));
+        assert_eq!(resumed.status(&details("c").id), None);
+        assert_eq!(resumed.context(), &context);
+    }
+
+    #[tokio::test]
+    async fn fails_to_resume_an_unknown_run() {
+        let dir = TempDir::new();
+
+        let run_id = uuid::Uuid::new_v4().to_string();
+
+        let result = RunJournal::resume_from(Path::new(dir.path()), &run_id).await;
+
+        assert_eq!(result.err().unwrap().exit_code(), 7);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_resume_a_run_id_outside_the_runs_directory() {
+        let dir = TempDir::new();
+        dir.write("x.json", r#"{"run_id": "x", "steps": {}}"#);
+        let runs = Path::new(dir.path()).join("runs");
+
+        let result = RunJournal::resume_from(&runs, "../x").await;
+
+        assert_eq!(result.err().unwrap().exit_code(), 6);
+    }
+}
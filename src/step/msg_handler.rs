@@ -2,11 +2,15 @@
 //! lifecycle events to.
 
 mod console;
+mod fan_out;
+mod json;
 mod progress_bars;
 
-use crate::step::event::{EventData, NewSequenceEvent, StepEvent};
-use crate::step::StepDetails;
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent, StepEvent};
+use crate::step::{StepDetails, StepStatus};
 pub use console::ConsoleLogEventHandler;
+pub use fan_out::FanOutEventHandler;
+pub use json::JsonEventHandler;
 pub use progress_bars::ProgressBarsEventHandler;
 
 /// The Steps are executed asynchronously, and on a multi-threaded machine can run on other thread.
@@ -39,6 +43,21 @@ pub trait EventHandler {
             StepEvent::Error(details) => {
                 self.step_error(step, details);
             }
+            StepEvent::Interrupt(details) => {
+                self.step_interrupted(step, details);
+            }
+            StepEvent::Retry(retry) => {
+                self.step_retry(step, retry);
+            }
+            StepEvent::Rollback(details) => {
+                self.step_rolled_back(step, details);
+            }
+            StepEvent::Output(details) => {
+                self.step_output(step, details);
+            }
+            StepEvent::Status(status, details) => {
+                self.step_status(step, status, details);
+            }
             _ => {}
         }
     }
@@ -49,4 +68,69 @@ pub trait EventHandler {
     fn step_skipped(&mut self, step: StepDetails, details: &EventData);
     fn step_end(&mut self, step: StepDetails, details: &EventData);
     fn step_error(&mut self, step: StepDetails, details: &EventData);
+    /// Called when the step timed out or was cancelled. The cause is the `error` of the event
+    /// data. By default handled the same as an error.
+    fn step_interrupted(&mut self, step: StepDetails, details: &EventData) {
+        self.step_error(step, details);
+    }
+    /// Called when an attempt of the step failed and it will be executed again.
+    fn step_retry(&mut self, _step: StepDetails, _retry: &RetryEvent) {}
+    /// Called once the step was undone, or undoing it failed, while rolling back the run.
+    fn step_rolled_back(&mut self, _step: StepDetails, _details: &EventData) {}
+    /// Called for every line of output of a process started by the step. The line is the `msg`
+    /// of the event data.
+    fn step_output(&mut self, _step: StepDetails, _details: &EventData) {}
+    /// Called for status changes of a step that do not have a dedicated event.
+    fn step_status(&mut self, _step: StepDetails, _status: StepStatus, _details: &EventData) {}
+}
+
+/// Lets the event handler be chosen at runtime, every event is passed on to the boxed handler.
+impl<T: EventHandler + ?Sized> EventHandler for Box<T> {
+    fn handle_event(&mut self, step: Option<StepDetails>, event: StepEvent<'_>) {
+        (**self).handle_event(step, event)
+    }
+
+    fn sequence_start(&mut self, details: NewSequenceEvent) {
+        (**self).sequence_start(details)
+    }
+
+    fn sequence_end(&mut self, details: &EventData) {
+        (**self).sequence_end(details)
+    }
+
+    fn step_start(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_start(step, details)
+    }
+
+    fn step_skipped(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_skipped(step, details)
+    }
+
+    fn step_end(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_end(step, details)
+    }
+
+    fn step_error(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_error(step, details)
+    }
+
+    fn step_interrupted(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_interrupted(step, details)
+    }
+
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        (**self).step_retry(step, retry)
+    }
+
+    fn step_rolled_back(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_rolled_back(step, details)
+    }
+
+    fn step_output(&mut self, step: StepDetails, details: &EventData) {
+        (**self).step_output(step, details)
+    }
+
+    fn step_status(&mut self, step: StepDetails, status: StepStatus, details: &EventData) {
+        (**self).step_status(step, status, details)
+    }
 }
@@ -1,7 +1,8 @@
 //! Optionally print all event messages to the console.
 
+use crate::error::OecliError;
 use crate::log::LogLevel;
-use crate::step::event::{EventData, NewSequenceEvent};
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent};
 use crate::step::msg_handler::EventHandler;
 use crate::step::StepDetails;
 
@@ -11,19 +12,17 @@ pub struct ConsoleLogEventHandler {
 }
 
 impl ConsoleLogEventHandler {
-    #[allow(dead_code)]
     pub fn new(msg_level: LogLevel) -> ConsoleLogEventHandler {
         ConsoleLogEventHandler { msg_level }
     }
 }
 
 impl EventHandler for ConsoleLogEventHandler {
-    fn sequence_start(&mut self, details: NewSequenceEvent) {}
-    fn sequence_end(&mut self, details: &EventData) {}
+    fn sequence_start(&mut self, _details: NewSequenceEvent) {}
+    fn sequence_end(&mut self, _details: &EventData) {}
 
     fn step_start(&mut self, step: StepDetails, data: &EventData) {
         match &self.msg_level {
-            LogLevel::Silent => {}
             LogLevel::Verbose => {
                 println!("{}\n{}\n{}", &step.title, &step.description, data.msg);
             }
@@ -36,7 +35,6 @@ impl EventHandler for ConsoleLogEventHandler {
 
     fn step_end(&mut self, step: StepDetails, data: &EventData) {
         match &self.msg_level {
-            LogLevel::Silent => {}
             LogLevel::Verbose => {
                 println!("{} Completed\n{}", &step.title, data.msg);
             }
@@ -49,7 +47,6 @@ impl EventHandler for ConsoleLogEventHandler {
 
     fn step_skipped(&mut self, step: StepDetails, data: &EventData) {
         match &self.msg_level {
-            LogLevel::Silent => {}
             LogLevel::Verbose => {
                 println!("{}: {}", &step.title, &data.msg);
             }
@@ -60,11 +57,52 @@ impl EventHandler for ConsoleLogEventHandler {
         }
     }
 
+    fn step_interrupted(&mut self, step: StepDetails, data: &EventData) {
+        if self.msg_level.shows(LogLevel::Error) {
+            println!("Interrupted: {}\n{}", &step.title, &data.msg);
+        }
+    }
+
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        if !self.msg_level.shows(LogLevel::Warning) {
+            return;
+        }
+        println!("Retry: {}\n{}", &step.title, &retry.data.msg);
+        if let (LogLevel::Verbose, Some(error)) = (&self.msg_level, &retry.data.error) {
+            println!("{}", error);
+        }
+    }
+
+    /// A failure to roll back is an error, a step that was rolled back a warning.
+    fn step_rolled_back(&mut self, step: StepDetails, data: &EventData) {
+        match &data.error {
+            Some(_) if self.msg_level.shows(LogLevel::Error) => {
+                println!("Rollback failed: {}\n{}", &step.title, &data.msg)
+            }
+            None if self.msg_level.shows(LogLevel::Warning) => {
+                println!("Rolled back: {}\n{}", &step.title, &data.msg)
+            }
+            _ => {}
+        }
+    }
+
+    fn step_output(&mut self, step: StepDetails, data: &EventData) {
+        if let LogLevel::Verbose = &self.msg_level {
+            println!("[{}] {}", &step.title, &data.msg);
+        }
+    }
+
     fn step_error(&mut self, step: StepDetails, data: &EventData) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                println!("Error: {}\n{}", &step.title, &data.msg);
+        if !self.msg_level.shows(LogLevel::Error) {
+            return;
+        }
+        println!("Error: {}\n{}", &step.title, &data.msg);
+        // The output of a failed process often explains the failure.
+        if let (LogLevel::Verbose, Some(OecliError::Exit { stdout, .. })) =
+            (&self.msg_level, &data.error)
+        {
+            if !stdout.trim().is_empty() {
+                println!("stdout:\n{}", stdout.trim_end());
             }
         }
     }
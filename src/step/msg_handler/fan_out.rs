@@ -0,0 +1,143 @@
+//! Publishes every event to several handlers at once.
+
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent};
+use crate::step::msg_handler::EventHandler;
+use crate::step::{StepDetails, StepStatus};
+
+/// Msg implementation that passes every event on to each of its handlers, in the order they were
+/// added. Every handler keeps its own [LogLevel](crate::log::LogLevel), so for example the
+/// terminal can stay quiet while a log file receives every event.
+///
+/// Example:
+///
+/// ```
+/// let mut msg_handler = FanOutEventHandler::new();
+/// msg_handler.push(ProgressBarsEventHandler::new(LogLevel::Info));
+/// msg_handler.push(JsonEventHandler::create("events.jsonl", LogLevel::Verbose)?);
+/// ```
+#[derive(Default)]
+pub struct FanOutEventHandler {
+    handlers: Vec<Box<dyn EventHandler + Send + Sync>>,
+}
+
+impl FanOutEventHandler {
+    pub fn new() -> FanOutEventHandler {
+        FanOutEventHandler::default()
+    }
+
+    pub fn push<T: EventHandler + Send + Sync + 'static>(&mut self, handler: T) {
+        self.handlers.push(Box::new(handler));
+    }
+}
+
+impl EventHandler for FanOutEventHandler {
+    fn sequence_start(&mut self, details: NewSequenceEvent) {
+        for handler in &mut self.handlers {
+            handler.sequence_start(NewSequenceEvent::new(
+                details.length,
+                &details.sequence_name,
+            ));
+        }
+    }
+
+    fn sequence_end(&mut self, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.sequence_end(details);
+        }
+    }
+
+    fn step_start(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_start(step.clone(), details);
+        }
+    }
+
+    fn step_skipped(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_skipped(step.clone(), details);
+        }
+    }
+
+    fn step_end(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_end(step.clone(), details);
+        }
+    }
+
+    fn step_error(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_error(step.clone(), details);
+        }
+    }
+
+    fn step_interrupted(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_interrupted(step.clone(), details);
+        }
+    }
+
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        for handler in &mut self.handlers {
+            handler.step_retry(step.clone(), retry);
+        }
+    }
+
+    fn step_rolled_back(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_rolled_back(step.clone(), details);
+        }
+    }
+
+    fn step_output(&mut self, step: StepDetails, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_output(step.clone(), details);
+        }
+    }
+
+    fn step_status(&mut self, step: StepDetails, status: StepStatus, details: &EventData) {
+        for handler in &mut self.handlers {
+            handler.step_status(step.clone(), status, details);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::{FakeStep, Recorded, RecordingEventHandler};
+    use crate::step::{ExecutorProperties, StepExecutor};
+
+    #[tokio::test(start_paused = true)]
+    async fn passes_every_event_to_each_handler() {
+        let (first, second) = (RecordingEventHandler::new(), RecordingEventHandler::new());
+        let mut fan_out = FanOutEventHandler::new();
+        fan_out.push(first.clone());
+        fan_out.push(second.clone());
+        let properties = ExecutorProperties::new()
+            .then_run(FakeStep::succeed("a").step())
+            .then_run(FakeStep::fail("b").step());
+
+        let _ = StepExecutor::from_executor_properties(properties, fan_out)
+            .run()
+            .await;
+
+        let events = |recorder: &RecordingEventHandler| {
+            recorder
+                .timed()
+                .into_iter()
+                .map(|(_, event)| event)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(events(&first), events(&second));
+        assert_eq!(
+            first.lifecycle(),
+            [
+                Recorded::SequenceStart("main".to_string(), 2),
+                Recorded::Start("a".to_string()),
+                Recorded::End("a".to_string()),
+                Recorded::Start("b".to_string()),
+                Recorded::Error("b".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Writes every event as a line of json, for tools that follow the progress of a run.
+
+use crate::error::OecliError;
+use crate::log::LogLevel;
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent};
+use crate::step::msg_handler::EventHandler;
+use crate::step::{StepDetails, StepId, StepStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// Msg implementation that writes one json object per event, each on its own line. Events of
+/// steps and sequences that finish include how long they ran for.
+///
+/// Errors and interruptions are written from [LogLevel::Error], retries and rollbacks from
+/// [LogLevel::Warning], the progress of every step from [LogLevel::Info] and the output and status
+/// changes of steps at [LogLevel::Verbose].
+pub struct JsonEventHandler {
+    pub msg_level: LogLevel,
+    out: Box<dyn Write + Send + Sync>,
+    started: HashMap<StepId, Instant>,
+    /// Start times of the sequences in progress, by title. A sequence nested within one with the
+    /// same title ends first, so it takes the latest start time.
+    sequences_started: HashMap<String, Vec<Instant>>,
+}
+
+/// A single line of the event stream. Fields that do not apply to the event are left out.
+#[derive(Default, Serialize)]
+struct JsonEvent<'a> {
+    event: &'a str,
+    /// Milliseconds since the unix epoch.
+    timestamp_ms: u128,
+    sequence: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    step_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempt: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_attempts: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delay_ms: Option<u128>,
+}
+
+impl JsonEventHandler {
+    /// Writes the events to stdout.
+    pub fn stdout(msg_level: LogLevel) -> JsonEventHandler {
+        JsonEventHandler::new(Box::new(std::io::stdout()), msg_level)
+    }
+
+    /// Writes the events to the file, replacing its contents.
+    pub fn create(path: &str, msg_level: LogLevel) -> Result<JsonEventHandler, OecliError> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| OecliError::io(&format!("Unable to create {}.", path), e))?;
+        Ok(JsonEventHandler::new(Box::new(file), msg_level))
+    }
+
+    pub fn new(out: Box<dyn Write + Send + Sync>, msg_level: LogLevel) -> JsonEventHandler {
+        JsonEventHandler {
+            msg_level,
+            out,
+            started: HashMap::new(),
+            sequences_started: HashMap::new(),
+        }
+    }
+
+    /// Writes the event as a single line when the log level shows events of the level. The event
+    /// stream is best effort, a failure to write it does not fail the run.
+    fn write(&mut self, level: LogLevel, event: JsonEvent) {
+        if !self.msg_level.shows(level) {
+            return;
+        }
+        let event = JsonEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis()),
+            ..event
+        };
+        if let Ok(line) = serde_json::to_string(&event) {
+            let _ = writeln!(self.out, "{}", line);
+            let _ = self.out.flush();
+        }
+    }
+
+    /// Milliseconds since the step started, once the step has finished.
+    fn finished(&mut self, step: &StepDetails) -> Option<u128> {
+        self.started
+            .remove(&step.id)
+            .map(|started| started.elapsed().as_millis())
+    }
+
+    fn step_event<'a>(event: &'a str, step: &'a StepDetails, data: &'a EventData) -> JsonEvent<'a> {
+        JsonEvent {
+            event,
+            sequence: &data.sequence_name,
+            step_id: Some(step.id.to_string()),
+            title: Some(&step.title),
+            message: Some(&data.msg),
+            error: data.error.as_ref().map(OecliError::to_string),
+            exit_code: data.error.as_ref().map(OecliError::exit_code),
+            ..JsonEvent::default()
+        }
+    }
+}
+
+impl EventHandler for JsonEventHandler {
+    fn sequence_start(&mut self, details: NewSequenceEvent) {
+        self.sequences_started
+            .entry(details.sequence_name.clone())
+            .or_default()
+            .push(Instant::now());
+        self.write(
+            LogLevel::Info,
+            JsonEvent {
+                event: "sequence_start",
+                sequence: &details.sequence_name,
+                length: Some(details.length),
+                ..JsonEvent::default()
+            },
+        );
+    }
+
+    fn sequence_end(&mut self, details: &EventData) {
+        let duration_ms = self
+            .sequences_started
+            .get_mut(&details.sequence_name)
+            .and_then(Vec::pop)
+            .map(|started| started.elapsed().as_millis());
+        self.write(
+            LogLevel::Info,
+            JsonEvent {
+                event: "sequence_end",
+                sequence: &details.sequence_name,
+                message: Some(&details.msg),
+                duration_ms,
+                ..JsonEvent::default()
+            },
+        );
+    }
+
+    fn step_start(&mut self, step: StepDetails, data: &EventData) {
+        self.started.insert(step.id, Instant::now());
+        self.write(
+            LogLevel::Info,
+            JsonEventHandler::step_event("step_start", &step, data),
+        );
+    }
+
+    fn step_skipped(&mut self, step: StepDetails, data: &EventData) {
+        let duration_ms = self.finished(&step);
+        self.write(
+            LogLevel::Info,
+            JsonEvent {
+                duration_ms,
+                ..JsonEventHandler::step_event("step_skipped", &step, data)
+            },
+        );
+    }
+
+    fn step_end(&mut self, step: StepDetails, data: &EventData) {
+        let duration_ms = self.finished(&step);
+        self.write(
+            LogLevel::Info,
+            JsonEvent {
+                duration_ms,
+                ..JsonEventHandler::step_event("step_end", &step, data)
+            },
+        );
+    }
+
+    fn step_error(&mut self, step: StepDetails, data: &EventData) {
+        let duration_ms = self.finished(&step);
+        self.write(
+            LogLevel::Error,
+            JsonEvent {
+                duration_ms,
+                ..JsonEventHandler::step_event("step_error", &step, data)
+            },
+        );
+    }
+
+    fn step_interrupted(&mut self, step: StepDetails, data: &EventData) {
+        let duration_ms = self.finished(&step);
+        self.write(
+            LogLevel::Error,
+            JsonEvent {
+                duration_ms,
+                ..JsonEventHandler::step_event("step_interrupted", &step, data)
+            },
+        );
+    }
+
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        self.write(
+            LogLevel::Warning,
+            JsonEvent {
+                attempt: Some(retry.attempt),
+                max_attempts: Some(retry.max_attempts),
+                delay_ms: Some(retry.delay.as_millis()),
+                ..JsonEventHandler::step_event("step_retry", &step, &retry.data)
+            },
+        );
+    }
+
+    /// A failure to roll back is an error.
+    fn step_rolled_back(&mut self, step: StepDetails, data: &EventData) {
+        let level = match data.error {
+            Some(_) => LogLevel::Error,
+            None => LogLevel::Warning,
+        };
+        self.write(
+            level,
+            JsonEventHandler::step_event("step_rolled_back", &step, data),
+        );
+    }
+
+    fn step_output(&mut self, step: StepDetails, data: &EventData) {
+        self.write(
+            LogLevel::Verbose,
+            JsonEventHandler::step_event("step_output", &step, data),
+        );
+    }
+
+    fn step_status(&mut self, step: StepDetails, status: StepStatus, data: &EventData) {
+        self.write(
+            LogLevel::Verbose,
+            JsonEvent {
+                status: Some(status.to_string()),
+                ..JsonEventHandler::step_event("step_status", &step, data)
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::FakeStep;
+    use crate::step::{ExecutorProperties, Step, StepExecutor, StepSequence};
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what the handler writes, clones share the buffer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs the steps and returns every line written, parsed.
+    async fn events(properties: ExecutorProperties, level: LogLevel) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let handler = JsonEventHandler::new(Box::new(buffer.clone()), level);
+        let _ = StepExecutor::from_executor_properties(properties, handler)
+            .run()
+            .await;
+        let out = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        out.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_a_line_for_every_event_of_the_level() {
+        let properties = ExecutorProperties::new()
+            .then_run(FakeStep::succeed("a").sleep(100).step())
+            .then_run(FakeStep::fail("b").step());
+
+        let events = events(properties, LogLevel::Info).await;
+
+        let names: Vec<&str> = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "sequence_start",
+                "step_start",
+                "step_end",
+                "step_start",
+                "step_error"
+            ]
+        );
+        assert_eq!(events[0]["sequence"], "main");
+        assert_eq!(events[0]["length"], 2);
+        assert_eq!(events[2]["title"], "a");
+        assert_eq!(events[2]["message"], "a done.");
+        assert_eq!(events[2]["duration_ms"], 100);
+        assert_eq!(events[4]["error"], "b failed.");
+        assert_eq!(events[4]["exit_code"], 6);
+        assert_eq!(events[1]["step_id"], events[2]["step_id"]);
+        assert!(events[4].get("attempt").is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_nested_sequences_that_share_a_title() {
+        let inner =
+            StepSequence::new("sequence", "").then_run(FakeStep::succeed("b").sleep(300).step());
+        let outer = StepSequence::new("sequence", "")
+            .then_run(FakeStep::succeed("a").sleep(100).step())
+            .then_run(Step::Sequence(inner));
+        let properties = ExecutorProperties::new().then_run(Step::Sequence(outer));
+
+        let events = events(properties, LogLevel::Info).await;
+
+        let durations: Vec<(&str, u64)> = events
+            .iter()
+            .filter(|event| event["event"] == "sequence_end")
+            .map(|event| {
+                (
+                    event["sequence"].as_str().unwrap(),
+                    event["duration_ms"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            durations,
+            [("sequence", 300), ("sequence", 400), ("main", 400)]
+        );
+    }
+}
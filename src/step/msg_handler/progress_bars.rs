@@ -1,7 +1,7 @@
 //! Uses ProgressBars per sequence to inform the user the progress of the current command ran.
 
 use crate::log::LogLevel;
-use crate::step::event::{EventData, NewSequenceEvent};
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent};
 use crate::step::msg_handler::EventHandler;
 use crate::step::StepDetails;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
@@ -29,84 +29,119 @@ impl EventHandler for ProgressBarsEventHandler {
     /// Any time a new sequence is started we want to ensure that a new progress bar is crated to
     /// track the progress of steps within that sequence.
     fn sequence_start(&mut self, details: NewSequenceEvent) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                if self.progress_bars.contains_key(&details.sequence_name) {
-                    return;
-                }
-                let pb = self
-                    .overall_progress
-                    .add(ProgressBar::new(details.length as u64));
-                let sty =
-                    ProgressStyle::with_template("[{pos:>4}/{len:4}] {bar:20} {msg}").unwrap();
-                pb.set_style(sty);
-                self.progress_bars.insert(details.sequence_name, pb);
-            }
+        if !self.msg_level.shows(LogLevel::Info)
+            || self.progress_bars.contains_key(&details.sequence_name)
+        {
+            return;
         }
+        let pb = self
+            .overall_progress
+            .add(ProgressBar::new(details.length as u64));
+        let sty = ProgressStyle::with_template("[{pos:>4}/{len:4}] {bar:20} {msg}").unwrap();
+        pb.set_style(sty);
+        self.progress_bars.insert(details.sequence_name, pb);
     }
 
     /// Once a sequence has completed we want to remove the progress bar associated with it.
     fn sequence_end(&mut self, event_details: &EventData) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                let sequence_name = event_details.sequence_name.to_owned();
-                let pb = self.progress_bars.get(&sequence_name);
-                if let Some(pb) = pb {
-                    pb.finish();
-                }
-            }
+        let pb = self.progress_bars.get(&event_details.sequence_name);
+        if let Some(pb) = pb {
+            pb.finish();
         }
     }
 
     /// Once a step starts we inform the user what step is starting and tick the progress bar to
     /// initiate rendering.
     fn step_start(&mut self, step: StepDetails, data: &EventData) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                let pb = self.progress_bars.get(&data.sequence_name);
-                if let Some(pb) = pb {
-                    pb.set_message(format!("{}", step.title));
-                    pb.tick();
-                }
-            }
+        let pb = self.progress_bars.get(&data.sequence_name);
+        if let Some(pb) = pb {
+            pb.set_message(step.title);
+            pb.tick();
         }
     }
 
     /// Anytime we bump the progress of a step we bump the associated progress bar.
     fn step_skipped(&mut self, step: StepDetails, data: &EventData) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                let pb = self.progress_bars.get(&data.sequence_name);
-                if let Some(pb) = pb {
-                    pb.set_message(format!(
-                        "{}: {} {}",
-                        step.title,
-                        data.msg.to_owned(),
-                        pb.position()
-                    ));
-                    pb.inc(1);
-                }
-            }
+        let pb = self.progress_bars.get(&data.sequence_name);
+        if let Some(pb) = pb {
+            pb.set_message(format!(
+                "{}: {} {}",
+                step.title,
+                data.msg.to_owned(),
+                pb.position()
+            ));
+            pb.inc(1);
         }
     }
 
     /// Once a step is completed successfully, we bump the progress of the progress bar.
     fn step_end(&mut self, _: StepDetails, data: &EventData) {
-        match &self.msg_level {
-            LogLevel::Silent => {}
-            _ => {
-                let pb = self.progress_bars.get(&data.sequence_name);
-                if let Some(pb) = pb {
-                    pb.inc(1)
-                }
+        let pb = self.progress_bars.get(&data.sequence_name);
+        if let Some(pb) = pb {
+            pb.inc(1)
+        }
+    }
+
+    /// When verbose, shows the latest line of output next to the step that is running.
+    fn step_output(&mut self, step: StepDetails, data: &EventData) {
+        if let LogLevel::Verbose = &self.msg_level {
+            let pb = self.progress_bars.get(&data.sequence_name);
+            if let Some(pb) = pb {
+                pb.set_message(format!("{}: {}", step.title, data.msg));
+            }
+        }
+    }
+
+    /// Shows that the step is being retried next to the step that is running. Without progress
+    /// bars the retry is printed as a warning.
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        match self.progress_bars.get(&retry.data.sequence_name) {
+            Some(pb) => pb.set_message(format!("{}: {}", step.title, retry.data.msg)),
+            None if self.msg_level.shows(LogLevel::Warning) => {
+                let line = format!("Retry: {}: {}", step.title, retry.data.msg);
+                let _ = self.overall_progress.println(line);
             }
+            None => {}
         }
     }
 
-    /// Error handling is handled at the top level.
-    fn step_error(&mut self, _step: StepDetails, _data: &EventData) {}
+    /// The progress bars have finished by the time the run rolls back, so every undone step is
+    /// printed above them. A failure to roll back is an error, a step that was rolled back a
+    /// warning.
+    fn step_rolled_back(&mut self, step: StepDetails, data: &EventData) {
+        let line = match &data.error {
+            Some(_) if self.msg_level.shows(LogLevel::Error) => {
+                format!("Rollback failed: {}: {}", step.title, data.msg)
+            }
+            None if self.msg_level.shows(LogLevel::Warning) => {
+                format!("Rolled back: {}: {}", step.title, data.msg)
+            }
+            _ => return,
+        };
+        let _ = self.overall_progress.println(line);
+    }
+
+    /// Leaves the progress bar of the sequence showing which step was interrupted. Without
+    /// progress bars the interruption is printed as an error.
+    fn step_interrupted(&mut self, step: StepDetails, data: &EventData) {
+        let msg = format!("{}: {}", step.title, data.msg);
+        match self.progress_bars.get(&data.sequence_name) {
+            Some(pb) => pb.abandon_with_message(msg),
+            None if self.msg_level.shows(LogLevel::Error) => {
+                let _ = self
+                    .overall_progress
+                    .println(format!("Interrupted: {}", msg));
+            }
+            None => {}
+        }
+    }
+
+    /// Prints the error above the progress bars, so a failure shows up as it happens rather than
+    /// only in the summary of the run.
+    fn step_error(&mut self, step: StepDetails, data: &EventData) {
+        if self.msg_level.shows(LogLevel::Error) {
+            let line = format!("Error: {}: {}", step.title, data.msg);
+            let _ = self.overall_progress.println(line);
+        }
+    }
 }
@@ -0,0 +1,22 @@
+//! Output of the processes a step starts can be streamed to the event handler while the step is
+//! executing. The executor sets up a sink for the duration of the step's `execute`, and
+//! the [SystemProcessRunner](crate::step::process::SystemProcessRunner) sends every line of output
+//! it reads to it.
+
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedSender;
+
+tokio::task_local! {
+    static STEP_OUTPUT: UnboundedSender<String>;
+}
+
+/// Runs the future with every line sent by [send_line] forwarded to the sender.
+pub async fn scope<F: Future>(sender: UnboundedSender<String>, f: F) -> F::Output {
+    STEP_OUTPUT.scope(sender, f).await
+}
+
+/// Sends a line of output to the step currently executing. Does nothing when called outside of a
+/// step.
+pub fn send_line(line: &str) {
+    let _ = STEP_OUTPUT.try_with(|sender| sender.send(line.to_string()));
+}
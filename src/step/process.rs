@@ -0,0 +1,192 @@
+//! Steps start programs through the [ProcessRunner] of the [RunContext](crate::step::RunContext)
+//! rather than spawning them directly. A run uses the [SystemProcessRunner], tests can swap it for
+//! one that returns canned output.
+
+use crate::error::OecliError;
+use crate::step::output;
+use async_trait::async_trait;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+/// A program to run, along with its arguments, working directory, environment and standard input.
+///
+/// Example:
+///
+/// ```
+/// let install = Invocation::new("npm").arg("install").current_dir("my-app");
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    pub current_dir: Option<PathBuf>,
+    /// Set on top of the environment of oecli.
+    pub env: Vec<(String, String)>,
+    pub stdin: Option<String>,
+}
+
+impl Invocation {
+    pub fn new(program: &str) -> Invocation {
+        Invocation {
+            program: program.to_string(),
+            ..Invocation::default()
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Invocation {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Invocation {
+        self.args
+            .extend(args.iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: &str) -> Invocation {
+        self.current_dir = Some(PathBuf::from(dir));
+        self
+    }
+
+    pub fn env(mut self, name: &str, value: &str) -> Invocation {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn stdin(mut self, input: &str) -> Invocation {
+        self.stdin = Some(input.to_string());
+        self
+    }
+
+    /// The program followed by its arguments.
+    pub fn argv(&self) -> Vec<&str> {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+/// Renders the program and arguments, quoting arguments that contain whitespace.
+impl Display for Invocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let quoted: Vec<_> = self
+            .argv()
+            .into_iter()
+            .map(|arg| match arg.contains(char::is_whitespace) {
+                true => format!("'{}'", arg),
+                false => arg.to_string(),
+            })
+            .collect();
+        write!(f, "{}", quoted.join(" "))
+    }
+}
+
+/// The result of a program that ran to completion.
+#[derive(Clone, Debug)]
+pub struct CommandOutput {
+    /// The rendered command line, used to report what was run.
+    pub command: String,
+    /// `None` when the process was terminated by a signal.
+    pub code: Option<i32>,
+    /// How long the process ran for.
+    pub duration: Duration,
+    /// Standard output, invalid UTF-8 is replaced.
+    pub stdout: String,
+    /// Standard error, invalid UTF-8 is replaced.
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Starts programs on behalf of the steps of a run.
+///
+/// While the process runs, every line it writes to stdout or stderr is streamed to the event
+/// handler of the step that is executing. If the step is interrupted the process is killed.
+#[async_trait]
+pub trait ProcessRunner: Send + Sync {
+    /// Runs the program to completion and captures its output. Only fails when the program could
+    /// not be run, the exit code is part of the output.
+    async fn run(&self, invocation: &Invocation) -> Result<CommandOutput, OecliError>;
+}
+
+/// Runs the programs as child processes of oecli.
+pub struct SystemProcessRunner;
+
+#[async_trait]
+impl ProcessRunner for SystemProcessRunner {
+    async fn run(&self, invocation: &Invocation) -> Result<CommandOutput, OecliError> {
+        let command_line = invocation.to_string();
+        let mut command = Command::new(&invocation.program);
+        command.args(&invocation.args);
+        if let Some(dir) = &invocation.current_dir {
+            command.current_dir(dir);
+        }
+        command.envs(invocation.env.iter().map(|(name, value)| (name, value)));
+        let stdin = invocation.stdin.as_deref();
+        let started = Instant::now();
+        let mut child = command
+            .kill_on_drop(true)
+            .stdin(match stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| OecliError::spawn(&command_line, e))?;
+        let write_stdin = async {
+            if let (Some(mut pipe), Some(input)) = (child.stdin.take(), stdin) {
+                // A process that exits without reading all of its input is not an error.
+                let _ = pipe.write_all(input.as_bytes()).await;
+            }
+        };
+        let (_, stdout, stderr) = tokio::join!(
+            write_stdin,
+            read_lines(child.stdout.take()),
+            read_lines(child.stderr.take()),
+        );
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| OecliError::io(&format!("Failed waiting on `{}`.", command_line), e))?;
+        Ok(CommandOutput {
+            command: command_line,
+            code: status.code(),
+            duration: started.elapsed(),
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Reads the stream line by line, sending each line to the step output, and returns everything
+/// that was read.
+async fn read_lines<R: AsyncRead + Unpin>(stream: Option<R>) -> String {
+    let mut stream = match stream {
+        Some(stream) => BufReader::new(stream),
+        None => return String::new(),
+    };
+    let mut contents = String::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match stream.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&line);
+                output::send_line(line.trim_end());
+                contents.push_str(&line);
+            }
+        }
+    }
+    contents
+}
@@ -0,0 +1,17 @@
+//! Steps can declare the resources they use, such as the network or the GitHub API. The executor
+//! limits how many steps use the same resource at once.
+
+use std::collections::BTreeMap;
+
+/// Any step that downloads or uploads.
+pub const NETWORK: &str = "network";
+/// Steps that run npm, which is heavy on the CPU and disk.
+pub const NPM: &str = "npm";
+/// Steps that call the GitHub API, directly or through the GitHub CLI.
+pub const GITHUB_API: &str = "github-api";
+
+/// Limits that apply unless they are configured otherwise. Concurrent requests quickly trip the
+/// secondary rate limits of GitHub.
+pub fn default_limits() -> BTreeMap<String, usize> {
+    BTreeMap::from([(GITHUB_API.to_string(), 4), (NPM.to_string(), 2)])
+}
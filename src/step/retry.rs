@@ -0,0 +1,72 @@
+//! Steps that talk to the network can fail for reasons that go away on their own. A step can
+//! declare a [RetryPolicy] and the executor will execute it again when it fails with an error the
+//! policy considers retryable.
+
+use crate::error::OecliError;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait before the next attempt.
+#[derive(Clone, Debug)]
+pub enum Backoff {
+    /// Wait the same amount of time before every attempt.
+    Fixed(Duration),
+    /// Double the wait after every attempt, starting at `initial` and never waiting longer than
+    /// `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// Decides whether a failed attempt should be retried.
+pub type RetryPredicate = Arc<dyn Fn(&OecliError) -> bool + Send + Sync>;
+
+/// Describes how often and how quickly a failing step is executed again.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Total number of times the step is executed, including the first attempt.
+    pub max_attempts: usize,
+    pub backoff: Backoff,
+    retryable: RetryPredicate,
+}
+
+impl RetryPolicy {
+    /// Retries every error except timeouts, cancellation and panics, which are never retried.
+    pub fn new(max_attempts: usize, backoff: Backoff) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff,
+            retryable: Arc::new(|_| true),
+        }
+    }
+
+    /// Only retry errors the predicate returns true for.
+    pub fn retry_if<F>(mut self, predicate: F) -> RetryPolicy
+    where
+        F: Fn(&OecliError) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Arc::new(predicate);
+        self
+    }
+
+    /// Whether the step should be executed again after `attempt` failed with the error. Attempts
+    /// start at 1.
+    pub fn should_retry(&self, attempt: usize, error: &OecliError) -> bool {
+        if matches!(
+            error,
+            OecliError::Timeout(_) | OecliError::Cancelled | OecliError::Panic { .. }
+        ) {
+            return false;
+        }
+        attempt < self.max_attempts && (self.retryable)(error)
+    }
+
+    /// How long to wait after `attempt` failed before starting the next one.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        match &self.backoff {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1) as u32);
+                initial.saturating_mul(factor).min(*max)
+            }
+        }
+    }
+}
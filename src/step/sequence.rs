@@ -1,7 +1,6 @@
 //! A series of steps that will run in series.
 
-use crate::step::{BoxedStepItem, Step, StepId};
-use std::collections::VecDeque;
+use crate::step::{Condition, FailureMode, Step};
 
 /// A series of steps that will run in series. Each individual step can itself be either a
 /// [StepItem]() or a StepSequence.
@@ -16,20 +15,22 @@ use std::collections::VecDeque;
 /// let seq = StepSequence::new("Unique Sequence Name", "Detailed Description")
 ///    .set_steps(vec![Step::Step(Box::new(step1))])
 ///    .then_run(Step::Step(Box::new(step2)));
-///    .then_parallel(vec![Step::Step(Box::new(step3)), Step::Step(Box::new(step4))]);
+///    .then_run_parallel(vec![Step::Step(Box::new(step3)), Step::Step(Box::new(step4))]);
 ///
 /// assert_eq!(seq.title, "Unique Sequence Name");
 /// assert_eq!(seq.description, "Detailed Description");
-/// assert_eq!(seq.num_step(), 4);
-/// assert_eq!(seq.has_next(), true);
 /// ```
 pub struct StepSequence {
-    /// Current iteration in the sequence.
-    cur: usize,
     /// A description of what is expected to happen within this sequence.
+    #[allow(dead_code)]
     pub description: String,
-    pub step_id: StepId,
-    pub steps: Vec<Vec<StepId>>,
+    /// Every step of the sequence is skipped unless all of these hold.
+    pub conditions: Vec<Condition>,
+    /// How the sequence reacts to a failed step. Inherited from the enclosing sequence when not
+    /// set.
+    pub failure_mode: Option<FailureMode>,
+    /// Steps in the order they will run. Steps within the same inner Vec run in parallel.
+    pub steps: Vec<Vec<Step>>,
     /// A unique name for the step sequence.
     pub title: String,
 }
@@ -37,44 +38,49 @@ pub struct StepSequence {
 impl StepSequence {
     pub fn new(title: &str, description: &str) -> StepSequence {
         StepSequence {
-            cur: 0,
+            conditions: Vec::new(),
             description: description.to_owned(),
-            step_id: StepId::new_random(),
+            failure_mode: None,
             steps: Vec::new(),
             title: title.to_owned(),
         }
     }
 
-    /// Returns the step id
-    pub fn get_id(&self) -> StepId {
-        self.step_id
-    }
-
-    pub fn get_next(&mut self) -> Vec<StepId> {
-        let step_ids = self.steps[self.cur];
-        self.cur = self.cur + 1;
-        step_ids
+    /// Sets how the sequence reacts when one of its steps fails.
+    #[allow(dead_code)]
+    pub fn with_failure_mode(mut self, failure_mode: FailureMode) -> StepSequence {
+        self.failure_mode = Some(failure_mode);
+        self
     }
 
-    /// Checks if there are any remaining steps to be processed in the step queue.
-    pub fn has_next(&self) -> bool {
-        self.cur < self.steps.len()
+    /// Skips every step of the sequence unless the condition holds. The condition is checked for
+    /// each step as it is reached.
+    pub fn skip_unless(mut self, condition: Condition) -> StepSequence {
+        self.conditions.push(condition);
+        self
     }
 
     /// Defines a number of steps to be run in parallel.
-    pub fn set_steps(mut self, steps: Vec<StepId>) -> StepSequence {
+    #[allow(dead_code)]
+    pub fn set_steps(mut self, steps: Vec<Step>) -> StepSequence {
         self.steps = vec![steps];
         self
     }
 
     /// Add a step to run at the end of the queue.
-    pub fn then_run(mut self, step: StepId) -> StepSequence {
+    pub fn then_run(mut self, step: Step) -> StepSequence {
         self.steps.push(vec![step]);
         self
     }
 
+    /// Add a step to run at the end of the queue, only when the condition holds.
+    pub fn then_run_if(self, step: Step, condition: Condition) -> StepSequence {
+        self.then_run(step.run_if(condition))
+    }
+
     /// Add several steps to run in parallel to the end of the queue.
-    pub fn then_run_parallel(mut self, steps: Vec<StepId>) -> StepSequence {
+    #[allow(dead_code)]
+    pub fn then_run_parallel(mut self, steps: Vec<Step>) -> StepSequence {
         self.steps.push(steps);
         self
     }
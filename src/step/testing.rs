@@ -0,0 +1,404 @@
+//! Fakes for testing the [StepExecutor](crate::step::StepExecutor) without running any programs.
+//! The [RecordingEventHandler] keeps every event it receives, [FakeStep] succeeds, skips, fails,
+//! sleeps or panics as it is told to and the [FakeProcessRunner] answers the programs steps start
+//! with canned output. Steps that edit files work within a [TempDir]. [run_command] runs the steps
+//! of a command against a [FakeProcessRunner].
+
+use crate::command::CLIStepExecutor;
+use crate::error::OecliError;
+use crate::step::event::{EventData, NewSequenceEvent, RetryEvent};
+use crate::step::msg_handler::EventHandler;
+use crate::step::process::{CommandOutput, Invocation, ProcessRunner};
+use crate::step::{
+    output, ExecutorProperties, RetryPolicy, RunContext, ShouldRunResult, Step, StepDetails,
+    StepExecutor, StepItem, StepStatus,
+};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// An event as it was received, steps are named by their title.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Recorded {
+    SequenceStart(String, usize),
+    SequenceEnd(String),
+    Start(String),
+    End(String),
+    Skip(String),
+    Error(String),
+    Interrupt(String),
+    Retry(String, usize),
+    RolledBack(String),
+    Output(String, String),
+    Status(String, StepStatus),
+}
+
+/// Records every event along with the time since the handler was created. Clones share the
+/// recording, so one clone can be handed to the executor while the test keeps the other.
+#[derive(Clone)]
+pub struct RecordingEventHandler {
+    created: Instant,
+    events: Arc<Mutex<Vec<(Duration, Recorded)>>>,
+}
+
+impl RecordingEventHandler {
+    pub fn new() -> RecordingEventHandler {
+        RecordingEventHandler {
+            created: Instant::now(),
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn record(&self, event: Recorded) {
+        let elapsed = self.created.elapsed();
+        self.events.lock().unwrap().push((elapsed, event));
+    }
+
+    /// Every event in the order it was received, with the time it was received at.
+    pub fn timed(&self) -> Vec<(Duration, Recorded)> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// The events in the order they were received, leaving out status changes and output.
+    pub fn lifecycle(&self) -> Vec<Recorded> {
+        self.timed()
+            .into_iter()
+            .map(|(_, event)| event)
+            .filter(|event| !matches!(event, Recorded::Status(..) | Recorded::Output(..)))
+            .collect()
+    }
+
+    /// When the event was first received.
+    pub fn at(&self, event: &Recorded) -> Option<Duration> {
+        self.timed()
+            .into_iter()
+            .find(|(_, recorded)| recorded == event)
+            .map(|(at, _)| at)
+    }
+}
+
+impl EventHandler for RecordingEventHandler {
+    fn sequence_start(&mut self, details: NewSequenceEvent) {
+        self.record(Recorded::SequenceStart(
+            details.sequence_name,
+            details.length,
+        ));
+    }
+
+    fn sequence_end(&mut self, details: &EventData) {
+        self.record(Recorded::SequenceEnd(details.sequence_name.clone()));
+    }
+
+    fn step_start(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::Start(step.title));
+    }
+
+    fn step_skipped(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::Skip(step.title));
+    }
+
+    fn step_end(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::End(step.title));
+    }
+
+    fn step_error(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::Error(step.title));
+    }
+
+    fn step_interrupted(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::Interrupt(step.title));
+    }
+
+    fn step_retry(&mut self, step: StepDetails, retry: &RetryEvent) {
+        self.record(Recorded::Retry(step.title, retry.attempt));
+    }
+
+    fn step_rolled_back(&mut self, step: StepDetails, _details: &EventData) {
+        self.record(Recorded::RolledBack(step.title));
+    }
+
+    fn step_output(&mut self, step: StepDetails, details: &EventData) {
+        self.record(Recorded::Output(step.title, details.msg.clone()));
+    }
+
+    fn step_status(&mut self, step: StepDetails, status: StepStatus, _details: &EventData) {
+        self.record(Recorded::Status(step.title, status));
+    }
+}
+
+enum Outcome {
+    Succeed,
+    Skip,
+    Fail,
+    Panic,
+    PanicOnCheck,
+}
+
+/// A step that sleeps for its delay and then does what it was created for.
+pub struct FakeStep {
+    title: String,
+    outcome: Outcome,
+    delay: Duration,
+    /// Attempts that fail before the step succeeds.
+    failures: usize,
+    retry_policy: Option<RetryPolicy>,
+    attempts: Arc<AtomicUsize>,
+    undoable: bool,
+}
+
+impl FakeStep {
+    fn new(title: &str, outcome: Outcome) -> FakeStep {
+        FakeStep {
+            title: title.to_string(),
+            outcome,
+            delay: Duration::ZERO,
+            failures: 0,
+            retry_policy: None,
+            attempts: Arc::new(AtomicUsize::new(0)),
+            undoable: false,
+        }
+    }
+
+    pub fn succeed(title: &str) -> FakeStep {
+        FakeStep::new(title, Outcome::Succeed)
+    }
+
+    /// `should_run` reports the step as already completed.
+    pub fn skip(title: &str) -> FakeStep {
+        FakeStep::new(title, Outcome::Skip)
+    }
+
+    /// Fails with a precondition error named after the step.
+    pub fn fail(title: &str) -> FakeStep {
+        FakeStep::new(title, Outcome::Fail)
+    }
+
+    pub fn panic(title: &str) -> FakeStep {
+        FakeStep::new(title, Outcome::Panic)
+    }
+
+    /// Panics in `should_run`, before the step is executed.
+    pub fn panic_on_check(title: &str) -> FakeStep {
+        FakeStep::new(title, Outcome::PanicOnCheck)
+    }
+
+    pub fn sleep(mut self, millis: u64) -> FakeStep {
+        self.delay = Duration::from_millis(millis);
+        self
+    }
+
+    /// Fails the first attempts before succeeding.
+    pub fn fail_first(mut self, failures: usize, retry_policy: RetryPolicy) -> FakeStep {
+        self.failures = failures;
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Undoes the step when the run is rolled back.
+    pub fn undoable(mut self) -> FakeStep {
+        self.undoable = true;
+        self
+    }
+
+    /// Counts how often the step is executed, shared with the step once it is boxed.
+    pub fn attempts(&self) -> Arc<AtomicUsize> {
+        self.attempts.clone()
+    }
+
+    pub fn step(self) -> Step {
+        Step::Step(Box::new(self))
+    }
+}
+
+#[async_trait]
+impl StepItem for FakeStep {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn description(&self) -> String {
+        String::new()
+    }
+
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
+        match self.outcome {
+            Outcome::Skip => ShouldRunResult::Skip,
+            Outcome::PanicOnCheck => panic!("{} panicked.", self.title),
+            _ => ShouldRunResult::Ok,
+        }
+    }
+
+    async fn execute(&self, _ctx: &RunContext) -> Result<String, OecliError> {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        tokio::time::sleep(self.delay).await;
+        if attempt <= self.failures {
+            return Err(OecliError::Precondition(format!(
+                "{} failed attempt {}.",
+                self.title, attempt
+            )));
+        }
+        match self.outcome {
+            Outcome::Succeed | Outcome::Skip | Outcome::PanicOnCheck => {
+                Ok(format!("{} done.", self.title))
+            }
+            Outcome::Fail => Err(OecliError::Precondition(format!("{} failed.", self.title))),
+            Outcome::Panic => panic!("{} panicked.", self.title),
+        }
+    }
+
+    async fn undo(&self, _ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        self.undoable.then(|| Ok(format!("{} undone.", self.title)))
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy.clone()
+    }
+}
+
+/// The canned result of a program started through the [FakeProcessRunner].
+#[derive(Clone)]
+pub struct FakeOutput {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+impl FakeOutput {
+    pub fn success(stdout: &str) -> FakeOutput {
+        FakeOutput {
+            code: 0,
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        }
+    }
+
+    pub fn failure(code: i32, stderr: &str) -> FakeOutput {
+        FakeOutput {
+            code,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        }
+    }
+}
+
+/// Answers invocations from a script instead of starting programs. An expectation matches the
+/// program and its arguments exactly, the first one that matches answers, as often as it matches.
+/// Invocations nothing matches fail the step. Clones share the record of calls, so one clone can
+/// be handed to the run while the test keeps the other.
+///
+/// Example:
+///
+/// ```
+/// let runner = FakeProcessRunner::new().expect(&["gh", "auth", "status"], FakeOutput::success(""));
+/// let ctx = runner.context();
+/// ```
+#[derive(Clone)]
+pub struct FakeProcessRunner {
+    expectations: Vec<(Vec<String>, FakeOutput)>,
+    calls: Arc<Mutex<Vec<Invocation>>>,
+}
+
+impl FakeProcessRunner {
+    pub fn new() -> FakeProcessRunner {
+        FakeProcessRunner {
+            expectations: Vec::new(),
+            calls: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn expect(mut self, argv: &[&str], output: FakeOutput) -> FakeProcessRunner {
+        let argv = argv.iter().map(|arg| arg.to_string()).collect();
+        self.expectations.push((argv, output));
+        self
+    }
+
+    /// Every invocation in the order it was made, including those nothing matched.
+    pub fn calls(&self) -> Vec<Invocation> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The program and arguments of every invocation.
+    pub fn argvs(&self) -> Vec<Vec<String>> {
+        self.calls()
+            .iter()
+            .map(|call| call.argv().into_iter().map(String::from).collect())
+            .collect()
+    }
+
+    /// The context of a run that starts its programs with this runner.
+    pub fn context(&self) -> RunContext {
+        RunContext::default().with_runner(Arc::new(self.clone()))
+    }
+}
+
+#[async_trait]
+impl ProcessRunner for FakeProcessRunner {
+    async fn run(&self, invocation: &Invocation) -> Result<CommandOutput, OecliError> {
+        self.calls.lock().unwrap().push(invocation.clone());
+        let output = self
+            .expectations
+            .iter()
+            .find(|(argv, _)| *argv == invocation.argv())
+            .map(|(_, output)| output)
+            .ok_or_else(|| {
+                OecliError::Internal(format!("Unexpected invocation `{}`.", invocation))
+            })?;
+        // Like the system runner, the output is streamed to the step as well.
+        for line in output.stdout.lines().chain(output.stderr.lines()) {
+            output::send_line(line);
+        }
+        Ok(CommandOutput {
+            command: invocation.to_string(),
+            code: Some(output.code),
+            duration: Duration::ZERO,
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+        })
+    }
+}
+
+/// Runs the steps of the command, starting their programs with the runner.
+pub async fn run_command<C: CLIStepExecutor>(
+    command: &C,
+    runner: &FakeProcessRunner,
+) -> Result<(), OecliError> {
+    let properties = command.set_properties(ExecutorProperties::new()).await;
+    let executor = StepExecutor::from_executor_properties(properties, RecordingEventHandler::new())
+        .with_process_runner(Arc::new(runner.clone()));
+    Ok(executor.run().await?)
+}
+
+/// A directory under the system temp directory, removed with everything in it when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        let path = std::env::temp_dir().join(format!("oecli-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    /// Writes the file at the path relative to the directory, creating the directories it is in.
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.0.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.0.join(path)).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -7,9 +7,13 @@ mod github;
 mod node;
 mod precommit;
 mod pwa;
+mod run;
+mod shell;
 
 #[cfg(feature = "testcmd")]
 use crate::command::CLIStepExecutor;
+use crate::command::ExecutorOptions;
+use crate::error::OecliError;
 use clap::Subcommand;
 
 /// OeCli commands.
@@ -19,18 +23,21 @@ pub enum Commands {
     Pwa(pwa::Pwa),
     /// Manage K3s (Kubernetes) cluster backed by flux and a GitOps workflow.
     CloudHome(cloud_home::CloudHome),
+    /// Run the steps declared in a workflow file.
+    Run(run::Run),
     #[cfg(feature = "testcmd")]
     /// Provides functionality to test the execution of commands and the output.
     Test(crate::test::TestCommand),
 }
 
 impl Commands {
-    pub async fn process(self) -> Result<(), String> {
+    pub async fn process(self, options: &ExecutorOptions) -> Result<(), OecliError> {
         match self {
-            Commands::Pwa(pwa) => pwa.process().await,
-            Commands::CloudHome(cloud) => cloud.process().await,
+            Commands::Pwa(pwa) => pwa.process(options).await,
+            Commands::CloudHome(cloud) => cloud.process(options).await,
+            Commands::Run(run) => run.process(options).await,
             #[cfg(feature = "testcmd")]
-            Commands::Test(t) => CLIStepExecutor::execute(&t).await,
+            Commands::Test(t) => CLIStepExecutor::execute(&t, options).await,
         }
     }
 }
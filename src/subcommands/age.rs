@@ -1,79 +1,165 @@
-use crate::command::cmd;
-use crate::step::{ShouldRunResult, StepItem};
+use crate::error::OecliError;
+use crate::step::{ContextKey, RunContext, ShouldRunResult, StepItem};
+use crate::subcommands::github::CLONED_PATH;
+use crate::subcommands::shell::{CommandTemplate, ShellStep};
 use async_trait::async_trait;
-use std::path::Path;
-use tokio::process::Command;
+use regex::Regex;
+use std::io::ErrorKind;
 
-///
-pub struct Age {
-    /// Name of the repository.
-    name: String,
+/// Public half of the age key generated for the repository.
+pub const AGE_PUBLIC_KEY: ContextKey<String> = ContextKey::new("age.public_key");
+
+fn key_path(name: &str) -> String {
+    format!("/home/oe/.config/sops/age/{}.txt", name)
 }
 
-impl Age {
-    pub fn new(name: &str) -> Age {
-        Age {
-            name: name.to_string(),
-        }
+/// Generates an age key for the repository under `~/.config/sops/age`. Skipped when the key
+/// exists, the key is removed again on rollback.
+pub fn generate_age_key(name: &str) -> ShellStep {
+    let key_path = key_path(name);
+    ShellStep::new(
+        "sh",
+        &[
+            "-c",
+            r#"mkdir -p "$(dirname "$1")" && age-keygen -o "$1""#,
+            "sh",
+            &key_path,
+        ],
+    )
+    .creates(&key_path)
+    .undo_with(CommandTemplate::new("rm", &[&key_path]))
+    .with_title("Set up age key for repo.")
+    .with_description("Sets up the age key and installs it to the home directory.")
+}
+
+/// Reads the public key from the age key of the repository into [AGE_PUBLIC_KEY]. Always runs,
+/// so the public key is known whether or not the key was generated during this run.
+pub fn read_age_public_key(name: &str) -> ShellStep {
+    ShellStep::new("age-keygen", &["-y", &key_path(name)])
+        .capture(AGE_PUBLIC_KEY.name())
+        .with_title("Read age public key.")
+        .with_description("Reads the public key of the age key of the repository.")
+}
+
+/// Sets `BOOTSTRAP_AGE_PUBLIC_KEY` in the `.config.env` of the cloned repository to the public
+/// key of the generated age key.
+pub struct ConfigureAgePublicKey;
+
+impl ConfigureAgePublicKey {
+    /// Reads the config, the public key and the path of the config.
+    async fn read_config(ctx: &RunContext) -> Result<(String, String, String), OecliError> {
+        let public_key = ctx.require(&AGE_PUBLIC_KEY)?;
+        let config_path = ctx.require(&CLONED_PATH)?.join(".config.env");
+        let config_path = config_path.display().to_string();
+        let config = tokio::fs::read_to_string(&config_path)
+            .await
+            .map_err(|e| OecliError::io(&format!("Unable to read {}.", config_path), e))?;
+        Ok((config, public_key, config_path))
     }
 }
 
+fn public_key_line(public_key: &str) -> String {
+    format!("BOOTSTRAP_AGE_PUBLIC_KEY=\"{}\"", public_key)
+}
+
 #[async_trait]
-impl StepItem for Age {
+impl StepItem for ConfigureAgePublicKey {
     fn title(&self) -> String {
-        "Set up age key for repo.".to_string()
+        "Add age public key to config.".to_string()
     }
 
     fn description(&self) -> String {
-        "Sets up the age key and installs it to the home directory.".to_string()
+        "Sets BOOTSTRAP_AGE_PUBLIC_KEY in .config.env to the public age key.".to_string()
     }
 
-    async fn should_run(&self) -> ShouldRunResult {
-        // Check home directory to see if age key exists
-        if Path::new(&format!("/home/oe/.config/sops/age/{}.txt", &self.name)).exists() {
-            ShouldRunResult::Skip
-        } else {
-            ShouldRunResult::Ok
+    /// Skips when the config already contains the key. Runs when the key or the repository are
+    /// not known yet, because the steps providing them have not run. A config that can not be
+    /// read for any other reason is an error.
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        if !ctx.contains(AGE_PUBLIC_KEY.name()) || !ctx.contains(CLONED_PATH.name()) {
+            return ShouldRunResult::Ok;
+        }
+        match ConfigureAgePublicKey::read_config(ctx).await {
+            Ok((config, public_key, _)) => {
+                if config
+                    .lines()
+                    .any(|line| line == public_key_line(&public_key))
+                {
+                    ShouldRunResult::Skip
+                } else {
+                    ShouldRunResult::Ok
+                }
+            }
+            Err(OecliError::Io { source, .. }) if source.kind() == ErrorKind::NotFound => {
+                ShouldRunResult::Ok
+            }
+            Err(e) => ShouldRunResult::Error(e),
         }
     }
 
-    ///
-    async fn execute(self: Box<Self>) -> Result<String, String> {
-        cmd(
-            || {
-                Command::new("age-keygen")
-                    .arg("-o")
-                    .arg(format!("age.agekey"))
-                    .output()
-            },
-            &self.name,
-        )
-        .await?;
-        cmd(
-            || {
-                Command::new("mkdir")
-                    .arg("-p")
-                    .arg(format!("/home/oe/.config/sops/age"))
-                    .output()
-            },
-            &self.name,
-        )
-        .await?;
-        cmd(
-            || {
-                Command::new("mv")
-                    .arg("age.agekey")
-                    .arg(format!("/home/oe/.config/sops/age/{}.txt", self.name))
-                    .output()
-            },
-            &self.name,
-        )
-        .await?;
-
-        // Add age key to .config.env
-        Ok(format!(
-            "Age key generated and moved to ~/.config/sops/age/{}.txt",
-            self.name,
-        ))
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let (config, public_key, config_path) = ConfigureAgePublicKey::read_config(ctx).await?;
+        let re = Regex::new(r"(?m)^BOOTSTRAP_AGE_PUBLIC_KEY=.*$").unwrap();
+        let line = public_key_line(&public_key);
+        let config = if re.is_match(&config) {
+            re.replace(&config, regex::NoExpand(&line)).into_owned()
+        } else {
+            format!("{}\n{}\n", config.trim_end_matches('\n'), line)
+        };
+        tokio::fs::write(&config_path, config)
+            .await
+            .map_err(|e| OecliError::io(&format!("Unable to write {}.", config_path), e))?;
+        Ok(format!("Added age public key to {}.", config_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::TempDir;
+    use std::path::PathBuf;
+
+    fn context(repo: &str) -> RunContext {
+        let ctx = RunContext::default();
+        ctx.set(&AGE_PUBLIC_KEY, "age1public".to_string()).unwrap();
+        ctx.set(&CLONED_PATH, PathBuf::from(repo)).unwrap();
+        ctx
+    }
+
+    #[tokio::test]
+    async fn skips_a_config_that_has_the_key() {
+        let repo = TempDir::new();
+        repo.write(".config.env", "BOOTSTRAP_AGE_PUBLIC_KEY=\"age1public\"\n");
+
+        let should_run = ConfigureAgePublicKey
+            .should_run(&context(repo.path()))
+            .await;
+
+        assert!(matches!(should_run, ShouldRunResult::Skip));
+    }
+
+    #[tokio::test]
+    async fn runs_before_the_repository_is_cloned() {
+        let repo = TempDir::new();
+        let missing = format!("{}/missing", repo.path());
+
+        let should_run = ConfigureAgePublicKey.should_run(&context(&missing)).await;
+
+        assert!(matches!(should_run, ShouldRunResult::Ok));
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_config_that_can_not_be_read() {
+        let repo = TempDir::new();
+        repo.write(".config.env/nested", "");
+
+        let should_run = ConfigureAgePublicKey
+            .should_run(&context(repo.path()))
+            .await;
+
+        match should_run {
+            ShouldRunResult::Error(e) => assert_eq!(e.exit_code(), 7),
+            _ => panic!("Expected the unreadable config to be an error."),
+        }
     }
 }
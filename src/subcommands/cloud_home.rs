@@ -1,9 +1,12 @@
 //! Commands for interacting with OECloud@Home installation.
 
 pub mod add;
+mod cluster;
 pub mod init;
+pub mod remove;
 
-use crate::command::CLIStepExecutor;
+use crate::command::{CLIStepExecutor, ExecutorOptions};
+use crate::error::OecliError;
 use clap::{Args, Subcommand};
 
 /// OECloud@Home is a kubernetes cluster meant to be installed barebones on x86, amd64, and arm.
@@ -25,11 +28,11 @@ pub struct CloudHome {
 }
 
 impl CloudHome {
-    pub async fn process(&self) -> Result<(), String> {
+    pub async fn process(&self, options: &ExecutorOptions) -> Result<(), OecliError> {
         match self.subcommand {
-            CloudHomeSubCommands::Add(ref args) => args.process(),
-            CloudHomeSubCommands::Init(ref args) => CLIStepExecutor::execute(args).await,
-            CloudHomeSubCommands::Remove => Ok(()),
+            CloudHomeSubCommands::Add(ref args) => CLIStepExecutor::execute(args, options).await,
+            CloudHomeSubCommands::Init(ref args) => CLIStepExecutor::execute(args, options).await,
+            CloudHomeSubCommands::Remove(ref args) => CLIStepExecutor::execute(args, options).await,
         }
     }
 }
@@ -42,5 +45,5 @@ pub enum CloudHomeSubCommands {
     /// Adds a new node to the Kubernetes Cluster.
     Add(add::Add),
     /// Removes a node from the Kubernetes Cluster.
-    Remove,
+    Remove(remove::Remove),
 }
@@ -1,16 +1,225 @@
 //! CLIStepExecutor for adding a new device to a OECloud@Home installation.
 
+use super::cluster::{
+    commit_node_files, kubectl, CheckNode, CloudConfig, Inventory, ListNode, Node, NodeRole,
+    INVENTORY, KUBECONFIG, PLAYBOOK,
+};
+use crate::command::CLIStepExecutor;
+use crate::error::OecliError;
+use crate::step::{
+    resource, Backoff, ExecutorProperties, RetryPolicy, RunContext, ShouldRunResult, Step,
+    StepItem, StepSequence,
+};
+use crate::subcommands::shell::{CommandTemplate, ShellStep};
+use async_trait::async_trait;
 use clap::Args;
+use std::time::Duration;
 
+/// Joins a new node to the cluster. The node is checked over SSH, listed in the inventory and
+/// `oecloudhome.toml` of the cluster repository and provisioned with the playbook of the
+/// repository. Once the node reports Ready, the change to the repository is committed.
 #[derive(Args, Clone, Debug)]
 pub struct Add {
     /// IP address of the new node to add to the Kubernetes Cluster.
     #[clap(long)]
     ip: String,
+    /// Name of the node within the cluster. Defaults to `k8s-` followed by the IP address.
+    #[clap(long)]
+    name: Option<String>,
+    /// Whether the node runs the control plane or only workloads.
+    #[clap(long, value_enum, default_value = "worker")]
+    role: NodeRole,
+    /// User to connect to the node with over SSH. Defaults to the user of the SSH config.
+    #[clap(long)]
+    user: Option<String>,
+    /// Directory of the cluster repository created by `cloud-home init`.
+    #[clap(long, default_value = ".")]
+    path: String,
 }
 
 impl Add {
-    pub fn process(&self) -> Result<(), String> {
-        Ok(())
+    fn node(&self) -> Node {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => format!("k8s-{}", self.ip.replace(['.', ':'], "-")),
+        };
+        Node {
+            name,
+            ip: self.ip.clone(),
+            role: self.role,
+            user: self.user.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl CLIStepExecutor for Add {
+    async fn set_properties(&self, cmd_props: ExecutorProperties) -> ExecutorProperties {
+        let node = self.node();
+        let path = &self.path;
+
+        // Provisioning the node is skipped once it is registered with the cluster.
+        let provision = ShellStep::new("ansible-playbook", &["-i", INVENTORY, PLAYBOOK])
+            .current_dir(path)
+            .env("KUBECONFIG", KUBECONFIG)
+            .unless(CommandTemplate::new(
+                "kubectl",
+                &["get", "node", &node.name],
+            ))
+            .with_title(&format!("Provision {}", node.name))
+            .with_description("Runs the playbook that installs k3s and joins the node.")
+            .with_resources(&[resource::NETWORK]);
+        let commit = commit_node_files(path, &format!("Add node {} ({})", node.name, node.ip));
+
+        let sequence = StepSequence::new(&format!("Add {} to the cluster", node.name), "")
+            .then_run(Step::Step(Box::new(CheckNode::new(&node))))
+            .then_run_parallel(vec![
+                Step::Step(Box::new(ListNode::<Inventory>::new(path, &node))),
+                Step::Step(Box::new(ListNode::<CloudConfig>::new(path, &node))),
+            ])
+            .then_run(Step::Step(Box::new(provision)))
+            .then_run(Step::Step(Box::new(WaitForNodeReady::new(
+                path, &node.name,
+            ))))
+            .then_run(Step::Step(Box::new(commit)));
+
+        cmd_props.then_run(Step::Sequence(sequence))
+    }
+}
+
+/// Waits for the node to join the cluster and report Ready. Asks kubectl every 10 seconds, for
+/// up to 5 minutes.
+struct WaitForNodeReady {
+    path: String,
+    name: String,
+}
+
+impl WaitForNodeReady {
+    fn new(path: &str, name: &str) -> WaitForNodeReady {
+        WaitForNodeReady {
+            path: path.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl StepItem for WaitForNodeReady {
+    fn title(&self) -> String {
+        format!("Wait for {} to be Ready", self.name)
+    }
+
+    fn description(&self) -> String {
+        format!("Waits for kubectl to report {} as Ready.", self.name)
+    }
+
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
+        ShouldRunResult::Ok
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let ready = kubectl(
+            &self.path,
+            &[
+                "get",
+                "node",
+                &self.name,
+                "-o",
+                r#"jsonpath={.status.conditions[?(@.type=="Ready")].status}"#,
+            ],
+        );
+        let output = ctx.run(&ready).await?;
+        if output.success() && output.stdout.trim() == "True" {
+            Ok(format!("{} is Ready.", self.name))
+        } else {
+            Err(OecliError::Precondition(format!(
+                "{} is not Ready yet.",
+                self.name
+            )))
+        }
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string()]
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        Some(
+            RetryPolicy::new(30, Backoff::Fixed(Duration::from_secs(10)))
+                .retry_if(|error| matches!(error, OecliError::Precondition(_))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cluster::commit_invocations;
+    use super::*;
+    use crate::step::testing::{run_command, FakeOutput, FakeProcessRunner, TempDir};
+
+    fn add(ip: &str, path: &str) -> Add {
+        Add {
+            ip: ip.to_string(),
+            name: None,
+            role: NodeRole::Worker,
+            user: Some("oe".to_string()),
+            path: path.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_the_node_and_commits_the_inventory() {
+        let repo = TempDir::new();
+        let add = add("192.168.1.21", repo.path());
+        let ssh = add.node().ssh("true");
+        let ready = r#"jsonpath={.status.conditions[?(@.type=="Ready")].status}"#;
+        let [unchanged, commit] =
+            commit_invocations(repo.path(), "Add node k8s-192-168-1-21 (192.168.1.21)");
+        let runner = FakeProcessRunner::new()
+            .expect(&ssh.argv(), FakeOutput::success(""))
+            .expect(
+                &["kubectl", "get", "node", "k8s-192-168-1-21"],
+                FakeOutput::failure(1, "Error from server (NotFound)"),
+            )
+            .expect(
+                &["ansible-playbook", "-i", INVENTORY, PLAYBOOK],
+                FakeOutput::success(""),
+            )
+            .expect(
+                &["kubectl", "get", "node", "k8s-192-168-1-21", "-o", ready],
+                FakeOutput::success("True"),
+            )
+            .expect(&unchanged.argv(), FakeOutput::failure(1, ""))
+            .expect(&commit.argv(), FakeOutput::success(""));
+
+        run_command(&add, &runner).await.unwrap();
+
+        let programs: Vec<_> = runner
+            .calls()
+            .into_iter()
+            .map(|call| call.program)
+            .collect();
+        assert_eq!(
+            programs,
+            ["ssh", "kubectl", "ansible-playbook", "kubectl", "sh", "sh"]
+        );
+        assert!(repo
+            .read(INVENTORY)
+            .contains("k8s-192-168-1-21:\n          ansible_host: 192.168.1.21"));
+        assert!(repo
+            .read("oecloudhome.toml")
+            .contains("[nodes.k8s-192-168-1-21]\nip = \"192.168.1.21\""));
+    }
+
+    #[tokio::test]
+    async fn stops_at_an_invalid_ip() {
+        let repo = TempDir::new();
+        let runner = FakeProcessRunner::new();
+
+        let result = run_command(&add("192.168.1.300", repo.path()), &runner).await;
+
+        assert_eq!(result.unwrap_err().exit_code(), 6);
+        assert!(runner.calls().is_empty());
+        assert!(!std::path::Path::new(repo.path()).join(INVENTORY).exists());
     }
 }
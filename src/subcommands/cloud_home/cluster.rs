@@ -0,0 +1,554 @@
+//! The files of the cluster repository that list the nodes of the cluster, and the tools used to
+//! talk to the nodes.
+//!
+//! Every node is listed twice: in the Ansible inventory the provisioning playbook runs against,
+//! and in `oecloudhome.toml`. Both are read and written through [NodeFile].
+
+use crate::error::OecliError;
+use crate::step::process::Invocation;
+use crate::step::{resource, RunContext, ShouldRunResult, StepItem};
+use crate::subcommands::shell::{CommandTemplate, ShellStep};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Ansible inventory of the cluster, relative to the repository.
+pub const INVENTORY: &str = "provision/ansible/inventory/hosts.yml";
+/// Playbook that installs k3s on the nodes of the inventory.
+pub const PLAYBOOK: &str = "provision/ansible/playbooks/cluster-installation.yml";
+/// Kubeconfig the playbook fetches from the cluster.
+pub const KUBECONFIG: &str = "provision/kubeconfig";
+
+/// Whether a node runs the control plane or only workloads. Named after the groups of the
+/// inventory.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeRole {
+    Master,
+    Worker,
+}
+
+impl NodeRole {
+    fn group(&self) -> &'static str {
+        match self {
+            NodeRole::Master => "master",
+            NodeRole::Worker => "worker",
+        }
+    }
+}
+
+/// A node of the cluster as it is listed in the repository.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub name: String,
+    pub ip: String,
+    pub role: NodeRole,
+    /// User to connect with over SSH, the SSH config decides when it is not set.
+    pub user: Option<String>,
+}
+
+impl Node {
+    /// Where SSH connects to, `user@ip` or only the IP.
+    pub fn ssh_target(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.ip),
+            None => self.ip.clone(),
+        }
+    }
+
+    /// Runs the command on the node over SSH. Never prompts, a node that requires a password
+    /// fails instead.
+    pub fn ssh(&self, command: &str) -> Invocation {
+        Invocation::new("ssh")
+            .args(&["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+            .arg(&self.ssh_target())
+            .arg(command)
+    }
+}
+
+/// Runs kubectl within the repository, against the cluster of its kubeconfig.
+pub fn kubectl(path: &str, args: &[&str]) -> Invocation {
+    Invocation::new("kubectl")
+        .args(args)
+        .current_dir(path)
+        .env("KUBECONFIG", KUBECONFIG)
+}
+
+/// A file of the cluster repository that lists the nodes of the cluster.
+#[async_trait]
+pub trait NodeFile: Sized + Send + Sync + 'static {
+    /// Path of the file, relative to the repository.
+    const PATH: &'static str;
+    /// How the file is referred to in titles.
+    const NAME: &'static str;
+
+    /// Parses the contents of the file, an empty file lists no nodes.
+    fn parse(contents: &str) -> Result<Self, String>;
+    fn render(&self) -> Result<String, String>;
+    /// The node with the name or IP address.
+    fn find(&self, name_or_ip: &str) -> Option<Node>;
+    /// Adds the node, replacing a node with the same name.
+    fn add(&mut self, node: &Node);
+    /// Removes the node with the name. Returns whether it was listed.
+    fn remove(&mut self, name: &str) -> bool;
+
+    /// Reads the file from the repository. A missing file lists no nodes.
+    async fn read(path: &str) -> Result<Self, OecliError> {
+        let file = Path::new(path).join(Self::PATH);
+        let contents = match tokio::fs::read_to_string(&file).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(OecliError::io(
+                    &format!("Unable to read {}.", file.display()),
+                    e,
+                ))
+            }
+        };
+        Self::parse(&contents).map_err(|e| {
+            OecliError::Precondition(format!("Unable to parse {}. {}", file.display(), e))
+        })
+    }
+
+    /// Writes the file to the repository, creating its directory when missing.
+    async fn write(&self, path: &str) -> Result<(), OecliError> {
+        let file = Path::new(path).join(Self::PATH);
+        let contents = self.render().map_err(|e| {
+            OecliError::Internal(format!("Unable to render {}. {}", file.display(), e))
+        })?;
+        let write = async {
+            if let Some(dir) = file.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            tokio::fs::write(&file, contents).await
+        };
+        write
+            .await
+            .map_err(|e| OecliError::io(&format!("Unable to write {}.", file.display()), e))
+    }
+}
+
+/// The Ansible inventory. Nodes are hosts of the group named after their role, under the
+/// `kubernetes` group.
+///
+/// ```yaml
+/// kubernetes:
+///   children:
+///     worker:
+///       hosts:
+///         k8s-1:
+///           ansible_host: 192.168.1.11
+/// ```
+pub struct Inventory(serde_yaml::Value);
+
+/// The mapping the value holds, replacing the value with an empty mapping when it holds anything
+/// else.
+fn mapping(value: &mut serde_yaml::Value) -> &mut serde_yaml::Mapping {
+    if !value.is_mapping() {
+        *value = serde_yaml::Value::Mapping(Default::default());
+    }
+    value.as_mapping_mut().expect("replaced by a mapping")
+}
+
+impl Inventory {
+    fn groups(&self) -> impl Iterator<Item = (&str, &serde_yaml::Mapping)> {
+        self.0["kubernetes"]["children"]
+            .as_mapping()
+            .into_iter()
+            .flatten()
+            .filter_map(|(group, hosts)| Some((group.as_str()?, hosts["hosts"].as_mapping()?)))
+    }
+}
+
+impl NodeFile for Inventory {
+    const PATH: &'static str = INVENTORY;
+    const NAME: &'static str = "the Ansible inventory";
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        match contents.trim() {
+            "" => Ok(Inventory(serde_yaml::Value::Mapping(Default::default()))),
+            contents => serde_yaml::from_str(contents)
+                .map(Inventory)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    fn render(&self) -> Result<String, String> {
+        serde_yaml::to_string(&self.0).map_err(|e| e.to_string())
+    }
+
+    fn find(&self, name_or_ip: &str) -> Option<Node> {
+        self.groups().find_map(|(group, hosts)| {
+            hosts.iter().find_map(|(name, host)| {
+                let name = name.as_str()?;
+                let ip = host["ansible_host"].as_str()?;
+                if name != name_or_ip && ip != name_or_ip {
+                    return None;
+                }
+                Some(Node {
+                    name: name.to_string(),
+                    ip: ip.to_string(),
+                    role: match group {
+                        "master" => NodeRole::Master,
+                        _ => NodeRole::Worker,
+                    },
+                    user: host["ansible_user"].as_str().map(String::from),
+                })
+            })
+        })
+    }
+
+    fn add(&mut self, node: &Node) {
+        self.remove(&node.name);
+        let mut host = serde_yaml::Mapping::new();
+        host.insert("ansible_host".into(), node.ip.clone().into());
+        if let Some(user) = &node.user {
+            host.insert("ansible_user".into(), user.clone().into());
+        }
+        let mut hosts = mapping(&mut self.0);
+        for key in ["kubernetes", "children", node.role.group(), "hosts"] {
+            hosts = mapping(hosts.entry(key.into()).or_insert(serde_yaml::Value::Null));
+        }
+        hosts.insert(node.name.clone().into(), host.into());
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let groups = self
+            .0
+            .get_mut("kubernetes")
+            .and_then(|kubernetes| kubernetes.get_mut("children"))
+            .and_then(serde_yaml::Value::as_mapping_mut);
+        let mut removed = false;
+        // A host listed in several groups is removed from every one of them.
+        for hosts in groups
+            .into_iter()
+            .flat_map(|groups| groups.values_mut())
+            .filter_map(|group| group.get_mut("hosts")?.as_mapping_mut())
+        {
+            removed |= hosts.remove(name).is_some();
+        }
+        removed
+    }
+}
+
+/// The nodes listed in `oecloudhome.toml`, one table per node.
+///
+/// ```toml
+/// [nodes.k8s-1]
+/// ip = "192.168.1.11"
+/// role = "worker"
+/// ```
+pub struct CloudConfig(toml::value::Table);
+
+/// A node of `oecloudhome.toml`, which is named by its table.
+#[derive(Serialize, Deserialize)]
+struct ConfigNode {
+    ip: String,
+    role: NodeRole,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+impl CloudConfig {
+    fn nodes(&self) -> impl Iterator<Item = Node> + '_ {
+        self.0
+            .get("nodes")
+            .and_then(toml::Value::as_table)
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, node)| {
+                let node: ConfigNode = node.clone().try_into().ok()?;
+                Some(Node {
+                    name: name.clone(),
+                    ip: node.ip,
+                    role: node.role,
+                    user: node.user,
+                })
+            })
+    }
+}
+
+impl NodeFile for CloudConfig {
+    const PATH: &'static str = "oecloudhome.toml";
+    const NAME: &'static str = "oecloudhome.toml";
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents)
+            .map(CloudConfig)
+            .map_err(|e| e.to_string())
+    }
+
+    fn render(&self) -> Result<String, String> {
+        // Values of the table are written before the tables within it only for a value.
+        toml::to_string(&toml::Value::Table(self.0.clone())).map_err(|e| e.to_string())
+    }
+
+    fn find(&self, name_or_ip: &str) -> Option<Node> {
+        self.nodes()
+            .find(|node| node.name == name_or_ip || node.ip == name_or_ip)
+    }
+
+    fn add(&mut self, node: &Node) {
+        let node_table = ConfigNode {
+            ip: node.ip.clone(),
+            role: node.role,
+            user: node.user.clone(),
+        };
+        let value = match toml::Value::try_from(node_table) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        let nodes = self
+            .0
+            .entry("nodes")
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+        if !nodes.is_table() {
+            *nodes = toml::Value::Table(Default::default());
+        }
+        if let Some(nodes) = nodes.as_table_mut() {
+            nodes.insert(node.name.clone(), value);
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        self.0
+            .get_mut("nodes")
+            .and_then(toml::Value::as_table_mut)
+            .is_some_and(|nodes| nodes.remove(name).is_some())
+    }
+}
+
+/// Lists the node in a file of the repository. Skipped when the node is already listed, fails
+/// when another node is listed under the same name or IP address. The node is removed again on
+/// rollback.
+pub struct ListNode<F> {
+    path: String,
+    node: Node,
+    file: PhantomData<F>,
+}
+
+impl<F: NodeFile> ListNode<F> {
+    pub fn new(path: &str, node: &Node) -> ListNode<F> {
+        ListNode {
+            path: path.to_string(),
+            node: node.clone(),
+            file: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: NodeFile> StepItem for ListNode<F> {
+    fn title(&self) -> String {
+        format!("Add {} to {}", self.node.name, F::NAME)
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Lists {} at {} as a {} in {}.",
+            self.node.name,
+            self.node.ip,
+            self.node.role.group(),
+            F::PATH
+        )
+    }
+
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
+        match F::read(&self.path).await {
+            Ok(file) if file.find(&self.node.name).as_ref() == Some(&self.node) => {
+                ShouldRunResult::Skip
+            }
+            Ok(_) => ShouldRunResult::Ok,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, _ctx: &RunContext) -> Result<String, OecliError> {
+        let mut file = F::read(&self.path).await?;
+        let conflict = [file.find(&self.node.name), file.find(&self.node.ip)]
+            .into_iter()
+            .flatten()
+            .find(|listed| *listed != self.node);
+        if let Some(listed) = conflict {
+            return Err(OecliError::Precondition(format!(
+                "{} already lists {} at {}.",
+                F::PATH,
+                listed.name,
+                listed.ip
+            )));
+        }
+        file.add(&self.node);
+        file.write(&self.path).await?;
+        Ok(format!("Added {} to {}.", self.node.name, F::PATH))
+    }
+
+    async fn undo(&self, _ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        let result = async {
+            let mut file = F::read(&self.path).await?;
+            file.remove(&self.node.name);
+            file.write(&self.path).await?;
+            Ok(format!("Removed {} from {}.", self.node.name, F::PATH))
+        };
+        Some(result.await)
+    }
+}
+
+/// Checks the node can be reached over SSH, so the playbook does not fail half way through.
+pub struct CheckNode {
+    node: Node,
+}
+
+impl CheckNode {
+    pub fn new(node: &Node) -> CheckNode {
+        CheckNode { node: node.clone() }
+    }
+}
+
+#[async_trait]
+impl StepItem for CheckNode {
+    fn title(&self) -> String {
+        format!("Check {} is reachable", self.node.ip)
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Checks {} is an IP address and connects to it over SSH.",
+            self.node.ip
+        )
+    }
+
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
+        ShouldRunResult::Ok
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        if self.node.ip.parse::<std::net::IpAddr>().is_err() {
+            return Err(OecliError::Precondition(format!(
+                "`{}` is not an IP address.",
+                self.node.ip
+            )));
+        }
+        let output = ctx.run(&self.node.ssh("true")).await?;
+        if !output.success() {
+            return Err(OecliError::Precondition(format!(
+                "Unable to reach {} over SSH. {}",
+                self.node.ssh_target(),
+                output.stderr.trim()
+            )));
+        }
+        Ok(format!("Reached {} over SSH.", self.node.ssh_target()))
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string()]
+    }
+}
+
+/// Commits the changes to the files that list the nodes. Skipped when neither file changed.
+pub fn commit_node_files(path: &str, message: &str) -> ShellStep {
+    let [unchanged, commit] = commit_invocations(path, message);
+    ShellStep::new(&commit.program, &commit.args)
+        .current_dir(path)
+        .unless(CommandTemplate::new(&unchanged.program, &unchanged.args))
+        .with_title(&format!("Commit \"{}\"", message))
+        .with_description(&format!(
+            "Commits the changes to {} and {}.",
+            INVENTORY,
+            CloudConfig::PATH
+        ))
+}
+
+/// What [commit_node_files] runs: the check whether the files changed, followed by the commit.
+pub fn commit_invocations(path: &str, message: &str) -> [Invocation; 2] {
+    let files = [INVENTORY, CloudConfig::PATH];
+    let unchanged = Invocation::new("sh")
+        .args(&[
+            "-c",
+            r#"status=$(git status --porcelain -- "$@") && test -z "$status""#,
+            "sh",
+        ])
+        .args(&files)
+        .current_dir(path);
+    let commit = Invocation::new("sh")
+        .args(&[
+            "-c",
+            r#"git add -- "$@" && git commit -m "$0" -- "$@""#,
+            message,
+        ])
+        .args(&files)
+        .current_dir(path);
+    [unchanged, commit]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::TempDir;
+
+    const HOSTS: &str = "kubernetes:
+  children:
+    master:
+      hosts:
+        k8s-0:
+          ansible_host: 192.168.1.10
+          ansible_user: oe
+";
+
+    fn worker(name: &str, ip: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            ip: ip.to_string(),
+            role: NodeRole::Worker,
+            user: None,
+        }
+    }
+
+    #[test]
+    fn lists_nodes_in_the_inventory_group_of_their_role() {
+        let mut inventory = Inventory::parse(HOSTS).unwrap();
+        inventory.add(&worker("k8s-1", "192.168.1.11"));
+
+        let inventory = Inventory::parse(&inventory.render().unwrap()).unwrap();
+        assert_eq!(
+            inventory.find("192.168.1.11"),
+            Some(worker("k8s-1", "192.168.1.11"))
+        );
+        let master = inventory.find("k8s-0").unwrap();
+        assert_eq!(master.role, NodeRole::Master);
+        assert_eq!(master.ssh_target(), "oe@192.168.1.10");
+    }
+
+    #[test]
+    fn removes_nodes_from_the_inventory() {
+        let mut inventory = Inventory::parse(HOSTS).unwrap();
+
+        assert!(inventory.remove("k8s-0"));
+        assert!(!inventory.remove("k8s-0"));
+        assert_eq!(inventory.find("k8s-0"), None);
+    }
+
+    #[test]
+    fn keeps_the_rest_of_the_cloud_config() {
+        let mut config = CloudConfig::parse("name = \"my-cloud\"\n").unwrap();
+        config.add(&worker("k8s-1", "192.168.1.11"));
+
+        let rendered = config.render().unwrap();
+        assert!(rendered.starts_with("name = \"my-cloud\"\n"));
+        let mut config = CloudConfig::parse(&rendered).unwrap();
+        assert_eq!(config.find("k8s-1"), Some(worker("k8s-1", "192.168.1.11")));
+        assert!(config.remove("k8s-1"));
+        assert_eq!(config.find("192.168.1.11"), None);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_list_a_node_at_an_ip_that_is_taken() {
+        let repo = TempDir::new();
+        repo.write(INVENTORY, HOSTS);
+        let step = ListNode::<Inventory>::new(repo.path(), &worker("k8s-1", "192.168.1.10"));
+
+        let result = step.execute(&RunContext::default()).await;
+        assert!(matches!(result, Err(OecliError::Precondition(_))));
+        assert_eq!(repo.read(INVENTORY), HOSTS);
+    }
+}
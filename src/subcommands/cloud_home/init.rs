@@ -1,11 +1,11 @@
 //! Initialize a new OECloud@Home installation.
 
 use crate::command::CLIStepExecutor;
-use crate::step::{ExecutorProperties, Step, StepSequence};
-use crate::subcommands::age::Age;
-use crate::subcommands::filesystem::{CopyFile, CreateFile};
+use crate::step::{ExecutorProperties, FailureMode, Step, StepGraph};
+use crate::subcommands::age::{generate_age_key, read_age_public_key, ConfigureAgePublicKey};
+use crate::subcommands::filesystem::{copy_file, create_file};
 use crate::subcommands::github::{CloneRepo, CreateTemplateRepo};
-use crate::subcommands::precommit::{PreCommit, PreCommitCommand};
+use crate::subcommands::precommit::{pre_commit, PreCommitCommand};
 use async_trait::async_trait;
 use clap::Args;
 
@@ -29,33 +29,34 @@ impl CLIStepExecutor for CloudHomeInit {
             CreateTemplateRepo::new(&name, "k8s-at-home/flux-cluster-template", public);
         let clone_repo = CloneRepo::new(&name);
 
-        let pwa_toml = CreateFile::new(&format!("{}/oecloudhome.toml", &name));
-        let cpy_config = CopyFile::new(
+        let pwa_toml = create_file(&format!("{}/oecloudhome.toml", &name));
+        let cpy_config = copy_file(
             &format!("{}/.config.sample.env", &name),
             &format!("{}/.config.env", &name),
         );
 
-        let precommit_init = PreCommit::new(PreCommitCommand::Init, &self.name);
-        let precommit_update = PreCommit::new(PreCommitCommand::Update, &self.name);
+        let precommit_init = pre_commit(PreCommitCommand::Init, &self.name);
+        let precommit_update = pre_commit(PreCommitCommand::Update, &self.name);
 
-        let precommit_sequence =
-            StepSequence::new("Run pre-commit hooks that come with the repository.", "")
-                .set_steps(vec![Step::Step(Box::new(precommit_init))])
-                .then_run(Step::Step(Box::new(precommit_update)));
-
-        // Configuration
-
-        let sequence = StepSequence::new("Set up cloud home repository", "")
-            .then_run(Step::Step(Box::new(create_template_repo)))
-            .then_run(Step::Step(Box::new(clone_repo)))
-            .then_run_parallel(vec![
-                Step::Step(Box::new(pwa_toml)),
-                Step::Sequence(precommit_sequence),
-                Step::Step(Box::new(cpy_config)),
-            ])
-            .then_run(Step::Step(Box::new(Age::new(&name))));
+        // Every step starts as soon as the repository is cloned. The configuration steps do not
+        // depend on each other, a failing pre-commit hook should not hide a failure to copy the
+        // config.
+        let mut graph = StepGraph::new("Set up cloud home repository", "")
+            .with_failure_mode(FailureMode::Continue);
+        let create_template_repo = graph.add(Box::new(create_template_repo), &[]);
+        let clone_repo = graph.add(Box::new(clone_repo), &[create_template_repo]);
+        graph.add(Box::new(pwa_toml), &[clone_repo]);
+        let cpy_config = graph.add(Box::new(cpy_config), &[clone_repo]);
+        let precommit_init = graph.add(Box::new(precommit_init), &[clone_repo]);
+        graph.add(Box::new(precommit_update), &[precommit_init]);
+        let age_key = graph.add(Box::new(generate_age_key(&name)), &[clone_repo]);
+        let age_public_key = graph.add(Box::new(read_age_public_key(&name)), &[age_key]);
+        graph.add(
+            Box::new(ConfigureAgePublicKey),
+            &[age_public_key, cpy_config],
+        );
 
-        cmd_props.then_run_parallel(vec![Step::Sequence(sequence)])
+        cmd_props.then_run(Step::Graph(graph))
     }
 }
 
@@ -110,33 +111,3 @@ impl CLIStepExecutor for CloudHomeInit {
 //if !std::env::var("SOPS_AGE_KEY_FILE").is_ok() {
 //std::env::set_var("SOPS_AGE_KEY_FILE", "/home/oe/.config/sops/age/keys.txt");
 //}
-//
-//// Parse .config.env and makesure the age public key exists under BOOTSTRAP_AGE_PUBLIC_KEY
-//let mut file = std::fs::File::open(".config.env").expect("Unable to read config.env");
-//let mut contents = String::new();
-//file.read_to_string(&mut contents)
-//.expect("Unable to read config.env");
-//let re = Regex::new("BOOTSTRAP_AGE_PUBLIC_KEY=\"(.*)\"$").unwrap();
-//
-//let mut age_public_key = std::fs::File::open("/home/oe/.config/sops/age/keys.txt")
-//.expect("Unable to read age public key");
-//let mut age_public_key_contents = String::new();
-//age_public_key
-//.read_to_string(&mut age_public_key_contents)
-//.expect("Unable to read age public key");
-//let re_pub_key = Regex::new("public key: (age.*)").unwrap();
-//let pub_key = re_pub_key
-//.captures(&age_public_key_contents)
-//.unwrap()
-//.get(1)
-//.unwrap()
-//.as_str();
-//
-//let caps = re.captures(&contents).unwrap();
-//if caps.get(1).is_none() || caps.get(1).unwrap().as_str() != pub_key {
-//let env_file = re.replace(&contents, |caps: &regex::Captures| {
-//format!("BOOTSTRAP_AGE_PUBLIC_KEY=\"{}\"", pub_key)
-//});
-//file.write_all(env_file.as_bytes())
-//.expect("Unable to write to config.env");
-//}
@@ -0,0 +1,538 @@
+//! CLIStepExecutor for removing a device from a OECloud@Home installation.
+
+use super::cluster::{
+    commit_node_files, kubectl, CloudConfig, Inventory, Node, NodeFile, NodeRole,
+};
+use crate::command::cmd;
+use crate::command::CLIStepExecutor;
+use crate::error::OecliError;
+use crate::step::{
+    resource, Condition, ContextKey, ExecutorProperties, RunContext, ShouldRunResult, Step,
+    StepItem, StepSequence,
+};
+use async_trait::async_trait;
+use clap::Args;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// The node being removed, as it was listed before the run removed it.
+pub const NODE: ContextKey<Node> = ContextKey::new("cloud_home.node");
+
+/// Removes a node from the cluster. The node is drained, k3s is uninstalled from it and it is
+/// deleted from Kubernetes. Then it is removed from the inventory and `oecloudhome.toml` of the
+/// cluster repository, and the change is committed.
+///
+/// k3s is uninstalled before the node is deleted, otherwise the agent registers the node again.
+/// With `--force` the node is not drained and k3s is left on it, for nodes that are no longer
+/// reachable.
+#[derive(Args, Clone, Debug)]
+pub struct Remove {
+    /// Name or IP address of the node, as it is listed in the inventory.
+    #[clap(long)]
+    node: String,
+    /// How long to wait for the pods of the node to be evicted. Evictions that would violate a
+    /// pod disruption budget are retried until then.
+    #[clap(long, value_name = "SECONDS", default_value = "300")]
+    drain_timeout: u64,
+    /// Delete a node that can not be drained, for example because it is dead.
+    #[clap(long)]
+    force: bool,
+    /// Directory of the cluster repository created by `cloud-home init`.
+    #[clap(long, default_value = ".")]
+    path: String,
+}
+
+#[async_trait]
+impl CLIStepExecutor for Remove {
+    async fn set_properties(&self, cmd_props: ExecutorProperties) -> ExecutorProperties {
+        let path = &self.path;
+        let force = self.force;
+        let not_forced =
+            Condition::new("--force is not set", move |_ctx| async move { Ok(!force) });
+
+        let drain = DrainNode {
+            path: path.to_string(),
+            timeout: Duration::from_secs(self.drain_timeout),
+        };
+        let commit = commit_node_files(path, &format!("Remove node {}", self.node));
+
+        let sequence = StepSequence::new(&format!("Remove {} from the cluster", self.node), "")
+            .then_run(Step::Step(Box::new(ResolveNode::new(path, &self.node))))
+            .then_run(Step::Step(not_forced.clone().guard(Box::new(drain))))
+            .then_run(Step::Step(not_forced.guard(Box::new(UninstallK3s))))
+            .then_run(Step::Step(Box::new(DeleteNode::new(path))))
+            .then_run_parallel(vec![
+                Step::Step(Box::new(UnlistNode::<Inventory>::new(path))),
+                Step::Step(Box::new(UnlistNode::<CloudConfig>::new(path))),
+            ])
+            .then_run(Step::Step(Box::new(commit)));
+
+        cmd_props.then_run(Step::Sequence(sequence))
+    }
+}
+
+/// Whether the node is registered with the cluster.
+async fn registered(ctx: &RunContext, path: &str, name: &str) -> Result<bool, OecliError> {
+    let output = ctx
+        .run(&kubectl(path, &["get", "node", name, "-o", "name"]))
+        .await?;
+    if output.success() {
+        Ok(true)
+    } else if output.stderr.contains("NotFound") {
+        Ok(false)
+    } else {
+        Err(OecliError::exit(&output))
+    }
+}
+
+/// Looks the node up in the inventory, or in `oecloudhome.toml`, and stores it in [NODE]. Later
+/// steps, and runs resuming this one, find the node there once it is no longer listed.
+struct ResolveNode {
+    path: String,
+    node: String,
+}
+
+impl ResolveNode {
+    fn new(path: &str, node: &str) -> ResolveNode {
+        ResolveNode {
+            path: path.to_string(),
+            node: node.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl StepItem for ResolveNode {
+    fn title(&self) -> String {
+        format!("Find node {}", self.node)
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Looks up {} in the inventory and oecloudhome.toml.",
+            self.node
+        )
+    }
+
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
+        ShouldRunResult::Ok
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let node = match Inventory::read(&self.path).await?.find(&self.node) {
+            Some(node) => Some(node),
+            None => CloudConfig::read(&self.path).await?.find(&self.node),
+        };
+        let node = node.ok_or_else(|| {
+            OecliError::Precondition(format!(
+                "{} is not listed in {} or {}.",
+                self.node,
+                Inventory::PATH,
+                CloudConfig::PATH
+            ))
+        })?;
+        let msg = format!("Found {} at {}.", node.name, node.ip);
+        ctx.set(&NODE, node)?;
+        Ok(msg)
+    }
+}
+
+/// Cordons the node and evicts its pods. Skipped when the node is no longer registered with the
+/// cluster. The node is uncordoned again on rollback.
+struct DrainNode {
+    path: String,
+    /// How long kubectl retries evictions a pod disruption budget does not allow yet.
+    timeout: Duration,
+}
+
+#[async_trait]
+impl StepItem for DrainNode {
+    fn title(&self) -> String {
+        "Drain node".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Cordons the node and evicts its pods, respecting pod disruption budgets.".to_string()
+    }
+
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        let registered = match ctx.require(&NODE) {
+            Ok(node) => registered(ctx, &self.path, &node.name).await,
+            // The node is only known once the step finding it ran.
+            Err(_) => Ok(true),
+        };
+        match registered {
+            Ok(true) => ShouldRunResult::Ok,
+            Ok(false) => ShouldRunResult::Skip,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let node = ctx.require(&NODE)?;
+        cmd(ctx, kubectl(&self.path, &["cordon", &node.name])).await?;
+        let timeout = format!("--timeout={}s", self.timeout.as_secs());
+        let drain = kubectl(
+            &self.path,
+            &[
+                "drain",
+                &node.name,
+                "--ignore-daemonsets",
+                "--delete-emptydir-data",
+                &timeout,
+            ],
+        );
+        match cmd(ctx, drain).await {
+            Ok(_) => Ok(format!("Drained {}.", node.name)),
+            Err(OecliError::Exit { stderr, .. }) if stderr.contains("disruption budget") => {
+                Err(OecliError::Precondition(format!(
+                    "Pod disruption budgets kept pods on {} from being evicted within {}s. Use \
+                     --force to remove the node anyway.\n{}",
+                    node.name,
+                    self.timeout.as_secs(),
+                    stderr.trim_end()
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Leaves kubectl the time to give up on its own and report which pods it could not evict.
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.timeout + Duration::from_secs(60))
+    }
+
+    async fn undo(&self, ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        let result = async {
+            let node = ctx.require(&NODE)?;
+            cmd(ctx, kubectl(&self.path, &["uncordon", &node.name])).await?;
+            Ok(format!("Uncordoned {}.", node.name))
+        };
+        Some(result.await)
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string()]
+    }
+}
+
+/// Runs the uninstall script k3s installed on the node over SSH. Skipped when the script is gone.
+struct UninstallK3s;
+
+impl UninstallK3s {
+    fn script(node: &Node) -> &'static str {
+        match node.role {
+            NodeRole::Master => "/usr/local/bin/k3s-uninstall.sh",
+            NodeRole::Worker => "/usr/local/bin/k3s-agent-uninstall.sh",
+        }
+    }
+
+    async fn installed(ctx: &RunContext) -> Result<bool, OecliError> {
+        let node = ctx.require(&NODE)?;
+        let output = ctx
+            .run(&node.ssh(&format!("test -x {}", UninstallK3s::script(&node))))
+            .await?;
+        match output.code {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            // ssh exits with 255 when it can not connect.
+            _ => Err(OecliError::Precondition(format!(
+                "Unable to reach {} over SSH. Use --force to remove a node that is no longer \
+                 reachable.\n{}",
+                node.ssh_target(),
+                output.stderr.trim_end()
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl StepItem for UninstallK3s {
+    fn title(&self) -> String {
+        "Uninstall k3s".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Runs the k3s uninstall script on the node over SSH.".to_string()
+    }
+
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        if !ctx.contains(NODE.name()) {
+            return ShouldRunResult::Ok;
+        }
+        match UninstallK3s::installed(ctx).await {
+            Ok(true) => ShouldRunResult::Ok,
+            Ok(false) => ShouldRunResult::Skip,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let node = ctx.require(&NODE)?;
+        let script = UninstallK3s::script(&node);
+        cmd(ctx, node.ssh(&format!("sudo -n {}", script))).await?;
+        Ok(format!("Uninstalled k3s from {}.", node.name))
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string()]
+    }
+}
+
+/// Deletes the node from Kubernetes. Skipped when it is no longer registered with the cluster.
+struct DeleteNode {
+    path: String,
+}
+
+impl DeleteNode {
+    fn new(path: &str) -> DeleteNode {
+        DeleteNode {
+            path: path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl StepItem for DeleteNode {
+    fn title(&self) -> String {
+        "Delete node from Kubernetes".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Deletes the node with kubectl, its pods are cleaned up by the cluster.".to_string()
+    }
+
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        let registered = match ctx.require(&NODE) {
+            Ok(node) => registered(ctx, &self.path, &node.name).await,
+            Err(_) => Ok(true),
+        };
+        match registered {
+            Ok(true) => ShouldRunResult::Ok,
+            Ok(false) => ShouldRunResult::Skip,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let node = ctx.require(&NODE)?;
+        cmd(ctx, kubectl(&self.path, &["delete", "node", &node.name])).await?;
+        Ok(format!("Deleted {}.", node.name))
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string()]
+    }
+}
+
+/// Removes the node from a file of the repository. Skipped when the file does not list it, the
+/// node is listed again on rollback.
+struct UnlistNode<F> {
+    path: String,
+    file: PhantomData<F>,
+}
+
+impl<F: NodeFile> UnlistNode<F> {
+    fn new(path: &str) -> UnlistNode<F> {
+        UnlistNode {
+            path: path.to_string(),
+            file: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: NodeFile> StepItem for UnlistNode<F> {
+    fn title(&self) -> String {
+        format!("Remove node from {}", F::NAME)
+    }
+
+    fn description(&self) -> String {
+        format!("Removes the node from {}.", F::PATH)
+    }
+
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        let node = match ctx.require(&NODE) {
+            Ok(node) => node,
+            Err(_) => return ShouldRunResult::Ok,
+        };
+        match F::read(&self.path).await {
+            Ok(file) if file.find(&node.name).is_some() => ShouldRunResult::Ok,
+            Ok(_) => ShouldRunResult::Skip,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let node = ctx.require(&NODE)?;
+        let mut file = F::read(&self.path).await?;
+        file.remove(&node.name);
+        file.write(&self.path).await?;
+        Ok(format!("Removed {} from {}.", node.name, F::PATH))
+    }
+
+    async fn undo(&self, ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        let result = async {
+            let node = ctx.require(&NODE)?;
+            let mut file = F::read(&self.path).await?;
+            file.add(&node);
+            file.write(&self.path).await?;
+            Ok(format!("Added {} to {} again.", node.name, F::PATH))
+        };
+        Some(result.await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cluster::commit_invocations;
+    use super::*;
+    use crate::step::testing::{run_command, FakeOutput, FakeProcessRunner, TempDir};
+
+    const HOSTS: &str = "kubernetes:
+  children:
+    worker:
+      hosts:
+        k8s-1:
+          ansible_host: 192.168.1.11
+          ansible_user: oe
+";
+    const CONFIG: &str = "[nodes.k8s-1]
+ip = \"192.168.1.11\"
+role = \"worker\"
+";
+
+    fn repo() -> TempDir {
+        let repo = TempDir::new();
+        repo.write(Inventory::PATH, HOSTS);
+        repo.write(CloudConfig::PATH, CONFIG);
+        repo
+    }
+
+    fn remove(path: &str, force: bool) -> Remove {
+        Remove {
+            node: "192.168.1.11".to_string(),
+            drain_timeout: 300,
+            force,
+            path: path.to_string(),
+        }
+    }
+
+    fn node() -> Node {
+        Node {
+            name: "k8s-1".to_string(),
+            ip: "192.168.1.11".to_string(),
+            role: NodeRole::Worker,
+            user: Some("oe".to_string()),
+        }
+    }
+
+    /// Expects the node to be registered, and deleted, and the change to be committed.
+    fn delete_and_commit(path: &str, runner: FakeProcessRunner) -> FakeProcessRunner {
+        let [unchanged, commit] = commit_invocations(path, "Remove node 192.168.1.11");
+        runner
+            .expect(
+                &["kubectl", "get", "node", "k8s-1", "-o", "name"],
+                FakeOutput::success("node/k8s-1"),
+            )
+            .expect(
+                &["kubectl", "delete", "node", "k8s-1"],
+                FakeOutput::success(""),
+            )
+            .expect(&unchanged.argv(), FakeOutput::failure(1, ""))
+            .expect(&commit.argv(), FakeOutput::success(""))
+    }
+
+    #[tokio::test]
+    async fn drains_uninstalls_and_deletes_the_node() {
+        let repo = repo();
+        let runner = delete_and_commit(repo.path(), FakeProcessRunner::new())
+            .expect(&["kubectl", "cordon", "k8s-1"], FakeOutput::success(""))
+            .expect(
+                &[
+                    "kubectl",
+                    "drain",
+                    "k8s-1",
+                    "--ignore-daemonsets",
+                    "--delete-emptydir-data",
+                    "--timeout=300s",
+                ],
+                FakeOutput::success(""),
+            )
+            .expect(
+                &node()
+                    .ssh("test -x /usr/local/bin/k3s-agent-uninstall.sh")
+                    .argv(),
+                FakeOutput::success(""),
+            )
+            .expect(
+                &node()
+                    .ssh("sudo -n /usr/local/bin/k3s-agent-uninstall.sh")
+                    .argv(),
+                FakeOutput::success(""),
+            );
+
+        run_command(&remove(repo.path(), false), &runner)
+            .await
+            .unwrap();
+
+        let argvs = runner.argvs();
+        let position = |arg: &str| argvs.iter().position(|argv| argv.iter().any(|a| a == arg));
+        assert!(position("drain") < position("sudo -n /usr/local/bin/k3s-agent-uninstall.sh"));
+        assert!(position("sudo -n /usr/local/bin/k3s-agent-uninstall.sh") < position("delete"));
+        assert!(Inventory::parse(&repo.read(Inventory::PATH))
+            .unwrap()
+            .find("k8s-1")
+            .is_none());
+        assert!(CloudConfig::parse(&repo.read(CloudConfig::PATH))
+            .unwrap()
+            .find("k8s-1")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn force_deletes_the_node_without_draining_it() {
+        let repo = repo();
+        let runner = delete_and_commit(repo.path(), FakeProcessRunner::new());
+
+        run_command(&remove(repo.path(), true), &runner)
+            .await
+            .unwrap();
+
+        let programs: Vec<_> = runner
+            .calls()
+            .into_iter()
+            .map(|call| call.program)
+            .collect();
+        assert_eq!(programs, ["kubectl", "kubectl", "sh", "sh"]);
+    }
+
+    #[tokio::test]
+    async fn points_to_force_when_a_disruption_budget_blocks_the_drain() {
+        let repo = repo();
+        let runner = delete_and_commit(repo.path(), FakeProcessRunner::new())
+            .expect(&["kubectl", "cordon", "k8s-1"], FakeOutput::success(""))
+            .expect(
+                &[
+                    "kubectl",
+                    "drain",
+                    "k8s-1",
+                    "--ignore-daemonsets",
+                    "--delete-emptydir-data",
+                    "--timeout=300s",
+                ],
+                FakeOutput::failure(
+                    1,
+                    "Cannot evict pod as it would violate the pod's disruption budget.",
+                ),
+            );
+
+        let error = run_command(&remove(repo.path(), false), &runner)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.exit_code(), 6);
+        assert!(error.to_string().contains("Use --force"));
+        assert_eq!(repo.read(Inventory::PATH), HOSTS);
+    }
+}
@@ -1,19 +1,86 @@
 //! Commands and various utilities for managing github repositories.
 
-use crate::step::{ShouldRunResult, StepItem};
+use crate::command::cmd;
+use crate::error::OecliError;
+use crate::step::process::Invocation;
+use crate::step::{
+    resource, Backoff, ContextKey, RetryPolicy, RunContext, ShouldRunResult, StepItem,
+};
 use async_trait::async_trait;
 use regex::Regex;
-use tokio::process::Command;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Login of the GitHub user the repositories are created for.
+pub const GITHUB_OWNER: ContextKey<String> = ContextKey::new("github.owner");
+/// Web address of the repository created from a template.
+pub const REPO_URL: ContextKey<String> = ContextKey::new("github.repo_url");
+/// Directory the repository was cloned to.
+pub const CLONED_PATH: ContextKey<PathBuf> = ContextKey::new("github.cloned_path");
+
+/// Talking to GitHub can fail on a flaky connection. Failed `gh` commands are retried, unless the
+/// repository got created by an attempt that failed part way through.
+fn github_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(
+        3,
+        Backoff::Exponential {
+            initial: Duration::from_secs(2),
+            max: Duration::from_secs(30),
+        },
+    )
+    .retry_if(|error| match error {
+        OecliError::Exit { stderr, .. } => !stderr.contains("already exists"),
+        _ => false,
+    })
+}
 
 /// Uses the GitHub CLI to check the authentication status. If we are logged in
 /// we use regex to extract the username between "github.com as" and "(..."
-async fn logged_in_user() -> String {
-    let cmd = Command::new("gh").arg("auth").arg("status").output().await;
-    // Should probably check the exit code here
-    let output = String::from_utf8(cmd.unwrap().stderr).unwrap();
+async fn logged_in_user(ctx: &RunContext) -> Result<String, OecliError> {
+    let output = ctx
+        .run(&Invocation::new("gh").args(&["auth", "status"]))
+        .await?;
+    // Depending on the version, gh writes the status to either stream.
+    let status = format!("{}{}", output.stdout, output.stderr);
+    match parse_logged_in_user(&status) {
+        Some(user) if output.success() => Ok(user),
+        _ => Err(OecliError::Auth(
+            "Make sure you are logged in to the GitHub CLI with `gh auth login`.".to_string(),
+        )),
+    }
+}
+
+/// Extracts the username from the output of `gh auth status`.
+fn parse_logged_in_user(status: &str) -> Option<String> {
     let re = Regex::new(r"github.com as (.*) \(").unwrap();
-    let cap = re.captures(&output).unwrap();
-    cap[1].to_owned()
+    re.captures(status).map(|cap| cap[1].to_owned())
+}
+
+/// The owner resolved by an earlier step, otherwise the user logged in to the GitHub CLI.
+async fn github_owner(ctx: &RunContext) -> Result<String, OecliError> {
+    if let Some(owner) = ctx.get(&GITHUB_OWNER)? {
+        return Ok(owner);
+    }
+    let owner = logged_in_user(ctx).await?;
+    ctx.set(&GITHUB_OWNER, owner.clone())?;
+    Ok(owner)
+}
+
+/// Names that already include the owner are left as they are.
+fn repo_url(owner: &str, name: &str) -> String {
+    match name.contains('/') {
+        true => format!("https://github.com/{}", name),
+        false => format!("https://github.com/{}/{}", owner, name),
+    }
+}
+
+/// Records where the repository is cloned to, relative paths are resolved against the current
+/// directory.
+fn set_cloned_path(ctx: &RunContext, repo_name: &str) -> Result<(), OecliError> {
+    let path = std::env::current_dir()
+        .map_err(|e| OecliError::io("Unable to read the current directory.", e))?
+        .join(repo_name);
+    ctx.set(&CLONED_PATH, path)
 }
 
 /// Clones a repo from github.com/<username>/<repo_name> to the current directory.
@@ -40,38 +107,37 @@ impl StepItem for CloneRepo {
     }
 
     /// Checks to see if the folder already exists.
-    async fn should_run(&self) -> ShouldRunResult {
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
         let folder_exists = std::path::Path::new(&self.repo_name).exists();
         if folder_exists {
-            return ShouldRunResult::Skip;
+            return match set_cloned_path(ctx, &self.repo_name) {
+                Ok(()) => ShouldRunResult::Skip,
+                Err(e) => ShouldRunResult::Error(e),
+            };
         }
-        return ShouldRunResult::Ok;
+        ShouldRunResult::Ok
     }
 
-    /// Uses [gh Cli]() to clone the provided repo for the current logged in user.
-    async fn execute(self: Box<Self>) -> Result<String, String> {
-        let username = logged_in_user().await;
+    /// Uses [gh Cli]() to clone the provided repo for the owner resolved by an earlier step, or
+    /// the current logged in user.
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let username = github_owner(ctx).await?;
         let full_repo = format!("{}/{}", &username, &self.repo_name);
-        let result = Command::new("gh")
-            .arg("repo")
-            .arg("clone")
-            .arg(full_repo)
-            .output()
-            .await;
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    Ok(format!("Repo {} cloned.", &self.repo_name))
-                } else {
-                    Err(format!(
-                        "Failed to clone repo {}.\n{}",
-                        &self.repo_name,
-                        String::from_utf8(output.stderr).unwrap()
-                    ))
-                }
-            }
-            Err(e) => Err(format!("Failed to clone repo {}.\n{}", &self.repo_name, e)),
-        }
+        let clone = Invocation::new("gh").args(&["repo", "clone", &full_repo]);
+        cmd(ctx, clone).await?;
+        set_cloned_path(ctx, &self.repo_name)?;
+        Ok(format!("Repo {} cloned.", &self.repo_name))
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![
+            resource::NETWORK.to_string(),
+            resource::GITHUB_API.to_string(),
+        ]
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        Some(github_retry_policy())
     }
 }
 
@@ -93,6 +159,21 @@ impl CreateTemplateRepo {
             public,
         }
     }
+
+    /// Whether the repository exists under the user logged in to the GitHub CLI. Stores the user
+    /// as the owner, and the address of the repository when it exists.
+    async fn exists(&self, ctx: &RunContext) -> Result<bool, OecliError> {
+        let owner = logged_in_user(ctx).await?;
+        ctx.set(&GITHUB_OWNER, owner.clone())?;
+        let view = ctx
+            .run(&Invocation::new("gh").args(&["repo", "view", &self.name]))
+            .await?;
+        if !view.success() {
+            return Ok(false);
+        }
+        ctx.set(&REPO_URL, repo_url(&owner, &self.name))?;
+        Ok(true)
+    }
 }
 
 #[async_trait]
@@ -109,86 +190,116 @@ impl StepItem for CreateTemplateRepo {
     /// Bool should be an associative array. If any are false, or bad result, log
     /// error for that key.
     ///
-    /// Checks if GH CLI is logged in and if the repo already exists.
-    async fn should_run(&self) -> ShouldRunResult {
-        let result = tokio::process::Command::new("gh")
-            .arg("auth")
-            .arg("status")
-            .output()
-            .await;
-        match result {
-            Ok(output) => {
-                if !output.status.success() {
-                    return ShouldRunResult::Error(
-                        "GitHub CLI authentication failed. Make sure you are logged in.".to_owned(),
-                    );
-                }
-            }
-            Err(e) => {
-                return ShouldRunResult::Error(format!(
-                    "Github CLI had an unexpected failure.\n{}",
-                    e.to_string(),
-                ));
-            }
+    /// Checks if GH CLI is logged in and if the repo already exists. Stores the logged in user as
+    /// the owner, and the address of the repository when it exists.
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        match self.exists(ctx).await {
+            Ok(true) => ShouldRunResult::Skip,
+            Ok(false) => ShouldRunResult::Ok,
+            Err(e) => ShouldRunResult::Error(e),
         }
-        let result = tokio::process::Command::new("gh")
-            .arg("repo")
-            .arg("view")
-            .arg(&self.name)
-            .output()
-            .await;
-        let err_str = format!(
-            "GraphQL: Could not resolve to a Repository with the name '{}/{}'. (repository)",
-            &self.repo, &self.name,
-        );
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    return ShouldRunResult::Skip;
-                }
-            }
-            Err(e) => {
-                if e.to_string() == err_str {
-                    return ShouldRunResult::Ok;
-                }
-                return ShouldRunResult::Error(format!(
-                    "Failed to check if repo {} exists.\n{}",
-                    &self.name,
-                    e.to_string(),
-                ));
-            }
-        }
-        ShouldRunResult::Ok
     }
 
     /// Users the [gh cli]() to create a new repo based on the provided template, under the current
     /// user.
-    async fn execute(self: Box<Self>) -> Result<String, String> {
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
         let visibility = if self.public { "--public" } else { "--private" };
 
-        let result = tokio::process::Command::new("gh")
-            .arg("repo")
-            .arg("create")
-            .arg(&self.name)
-            .arg("--template")
-            .arg(self.repo)
-            .arg(visibility)
-            .output()
-            .await;
-
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    Ok(format!("Created Github repository {}", self.name))
-                } else {
-                    Err(format!(
-                        "Failed to create repo {}.\n{}",
-                        &self.name,
-                        String::from_utf8(output.stderr).unwrap()
-                    ))
-                }
-            }
-            Err(e) => Err(format!("Failed to create repo {}.\n{}", &self.name, e)),
-        }
+        let create = Invocation::new("gh").args(&[
+            "repo",
+            "create",
+            &self.name,
+            "--template",
+            &self.repo,
+            visibility,
+        ]);
+        cmd(ctx, create).await?;
+        let owner = github_owner(ctx).await?;
+        ctx.set(&REPO_URL, repo_url(&owner, &self.name))?;
+        Ok(format!("Created Github repository {}", self.name))
+    }
+
+    /// Deletes the repository that was created. Requires the `delete_repo` scope, which can be
+    /// granted with `gh auth refresh -s delete_repo`.
+    async fn undo(&self, ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        let delete = Invocation::new("gh").args(&["repo", "delete", &self.name, "--yes"]);
+        Some(
+            cmd(ctx, delete)
+                .await
+                .map(|_| format!("Deleted Github repository {}", self.name)),
+        )
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![
+            resource::NETWORK.to_string(),
+            resource::GITHUB_API.to_string(),
+        ]
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        Some(github_retry_policy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::{FakeOutput, FakeProcessRunner};
+
+    const LOGGED_IN: &str = "github.com\n  ✓ Logged in to github.com as octocat (keyring)\n";
+
+    #[tokio::test]
+    async fn skips_a_template_repo_that_exists() {
+        let runner = FakeProcessRunner::new()
+            .expect(&["gh", "auth", "status"], FakeOutput::success(LOGGED_IN))
+            .expect(&["gh", "repo", "view", "demo"], FakeOutput::success(""));
+        let ctx = runner.context();
+        let step = CreateTemplateRepo::new("demo", "org/template", false);
+
+        assert!(matches!(step.should_run(&ctx).await, ShouldRunResult::Skip));
+        assert_eq!(
+            ctx.get(&REPO_URL).unwrap(),
+            Some("https://github.com/octocat/demo".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn requires_a_logged_in_user() {
+        let runner = FakeProcessRunner::new().expect(
+            &["gh", "auth", "status"],
+            FakeOutput::failure(1, "You are not logged into any GitHub hosts."),
+        );
+        let step = CreateTemplateRepo::new("demo", "org/template", false);
+
+        assert!(matches!(
+            step.should_run(&runner.context()).await,
+            ShouldRunResult::Error(OecliError::Auth(_))
+        ));
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn creates_a_private_repo_from_the_template() {
+        let runner = FakeProcessRunner::new()
+            .expect(&["gh", "auth", "status"], FakeOutput::success(LOGGED_IN))
+            .expect(
+                &[
+                    "gh",
+                    "repo",
+                    "create",
+                    "demo",
+                    "--template",
+                    "org/template",
+                    "--private",
+                ],
+                FakeOutput::success(""),
+            );
+        let ctx = runner.context();
+        let step = CreateTemplateRepo::new("demo", "org/template", false);
+
+        assert!(step.execute(&ctx).await.is_ok());
+        assert_eq!(runner.argvs()[0][..3], ["gh", "repo", "create"]);
+        assert_eq!(ctx.get(&GITHUB_OWNER).unwrap(), Some("octocat".to_string()));
     }
 }
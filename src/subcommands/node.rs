@@ -1,6 +1,9 @@
 //! Commands for interacting with Node and Node Package Manager CLI tools.
 
-use crate::step::{ShouldRunResult, StepItem};
+use crate::command::cmd;
+use crate::error::OecliError;
+use crate::step::process::Invocation;
+use crate::step::{resource, RunContext, ShouldRunResult, StepItem};
 use async_trait::async_trait;
 
 /// Runs [npm install]() in the provided directory.
@@ -27,19 +30,25 @@ impl StepItem for NPMInstall {
         "Will run 'npm install' in the newly provided directory".to_string()
     }
 
-    async fn should_run(&self) -> ShouldRunResult {
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
         // Check to make sure the directory exists
         ShouldRunResult::Ok
     }
 
     /// Runs `npm install` in the provided directory.
-    /// TODO: Parse the output and return the correct error.
-    async fn execute(self: Box<Self>) -> Result<String, String> {
-        let _ = tokio::process::Command::new("gh")
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let npm = Invocation::new("npm")
             .arg("install")
-            .current_dir(format!("./{}", &self.path))
-            .output()
-            .await;
-        Ok("".to_string())
+            .current_dir(&format!("./{}", &self.path));
+        let output = cmd(ctx, npm).await?;
+        Ok(format!(
+            "Installed packages in {} ({:.1}s).",
+            self.path,
+            output.duration.as_secs_f32()
+        ))
+    }
+
+    fn resources(&self) -> Vec<String> {
+        vec![resource::NETWORK.to_string(), resource::NPM.to_string()]
     }
 }
@@ -1,6 +1,5 @@
-use crate::step::{ShouldRunResult, StepItem};
-use async_trait::async_trait;
-use tokio::process::Command;
+use crate::step::resource;
+use crate::subcommands::shell::ShellStep;
 
 pub enum PreCommitCommand {
     Init,
@@ -16,71 +15,13 @@ impl PreCommitCommand {
     }
 }
 
-/// Set up pre-commit hooks that come with the repository.
-pub struct PreCommit {
-    command: PreCommitCommand,
-    // path to run under
-    path: String,
-}
-
-impl PreCommit {
-    pub fn new(command: PreCommitCommand, path: &str) -> PreCommit {
-        PreCommit {
-            command,
-            path: path.to_string(),
-        }
-    }
-}
-
-#[async_trait]
-impl StepItem for PreCommit {
-    fn title(&self) -> String {
-        format!("Running task {}", self.command.precommit_subcommand())
-    }
-
-    fn description(&self) -> String {
-        format!(
-            "Runs the pre-commit {} command",
-            self.command.precommit_subcommand()
-        )
-    }
-
-    async fn should_run(&self) -> ShouldRunResult {
-        match self.command {
-            PreCommitCommand::Init => ShouldRunResult::Ok,
-            PreCommitCommand::Update => ShouldRunResult::Ok,
-        }
-    }
-
-    ///
-    async fn execute(self: Box<Self>) -> Result<String, String> {
-        let result = Command::new("task")
-            .current_dir(format!("./{}", &self.path))
-            .arg(self.command.precommit_subcommand())
-            .output()
-            .await;
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    return Ok(format!(
-                        "Running `task {}`",
-                        self.command.precommit_subcommand()
-                    ));
-                } else {
-                    return Err(format!(
-                        "Error Running `task {}` Msg: {}",
-                        self.command.precommit_subcommand(),
-                        String::from_utf8(output.stderr).unwrap()
-                    ));
-                }
-            }
-            Err(e) => {
-                return Err(format!(
-                    "Running `task {}` Msg: {}",
-                    self.command.precommit_subcommand(),
-                    e.to_string(),
-                ));
-            }
-        }
-    }
+/// Set up pre-commit hooks that come with the repository, by running the task of the repository
+/// under `path`. Installing and updating the hooks downloads them.
+pub fn pre_commit(command: PreCommitCommand, path: &str) -> ShellStep {
+    let subcommand = command.precommit_subcommand();
+    ShellStep::new("task", &[&subcommand])
+        .current_dir(&format!("./{}", path))
+        .with_title(&format!("Running task {}", subcommand))
+        .with_description(&format!("Runs the pre-commit {} command", subcommand))
+        .with_resources(&[resource::NETWORK])
 }
@@ -2,7 +2,8 @@
 
 mod create;
 
-use crate::command::CLIStepExecutor;
+use crate::command::{CLIStepExecutor, ExecutorOptions};
+use crate::error::OecliError;
 use clap::{Args, Subcommand};
 
 /// Oecli subcommand for interacting with Progressive Web Apps. Overengineered uses Yew, a modern
@@ -26,9 +27,9 @@ pub struct Pwa {
 }
 
 impl Pwa {
-    pub async fn process(&self) -> Result<(), String> {
+    pub async fn process(&self, options: &ExecutorOptions) -> Result<(), OecliError> {
         if let PwaSubCommands::Create(ref create) = self.subcommand {
-            return CLIStepExecutor::execute(create).await;
+            return CLIStepExecutor::execute(create, options).await;
         }
         Ok(())
     }
@@ -1,7 +1,7 @@
 //! Creates a new progressive web app.
 
 use crate::command::CLIStepExecutor;
-use crate::step::{ExecutorProperties, Step, StepSequence};
+use crate::step::{Condition, ExecutorProperties, Step, StepSequence};
 use crate::subcommands::github::{CloneRepo, CreateTemplateRepo};
 use crate::subcommands::node::NPMInstall;
 use async_trait::async_trait;
@@ -23,7 +23,7 @@ pub struct PwaCreate {
 impl CLIStepExecutor for PwaCreate {
     /// Create the new template repository from `ctron/patternfly-yew-quickstart` and clone that
     /// repository into a new subdirectory with the same name. Under the new directory, runs `npm
-    /// install`.
+    /// install` when the repository has a `package.json`.
     async fn set_properties(&self, cmd_props: ExecutorProperties) -> ExecutorProperties {
         let name = &self.name;
         let public = self.public.unwrap_or(false);
@@ -36,7 +36,10 @@ impl CLIStepExecutor for PwaCreate {
         let sequence = StepSequence::new("Set up cloud home repository", "")
             .then_run(Step::Step(Box::new(create_template_repo)))
             .then_run(Step::Step(Box::new(clone_repo)))
-            .then_run(Step::Step(Box::new(npm_install)));
+            .then_run_if(
+                Step::Step(Box::new(npm_install)),
+                Condition::path_exists(&format!("{}/package.json", name)),
+            );
 
         cmd_props.then_run_parallel(vec![Step::Sequence(sequence)])
     }
@@ -0,0 +1,28 @@
+//! Runs the steps declared in a workflow file.
+
+mod workflow;
+
+use crate::command::{CLIStepExecutor, ExecutorOptions};
+use crate::error::OecliError;
+use clap::Args;
+use std::path::PathBuf;
+use workflow::Workflow;
+
+/// Loads a pipeline of built-in steps from a TOML or YAML file and runs it, so new boilerplate
+/// flows do not require a new subcommand.
+///
+/// Usage:
+/// `oecli run <workflow.toml|workflow.yaml>`
+#[derive(Args, Debug)]
+pub struct Run {
+    /// Path to the workflow file, ending in `.toml`, `.yaml` or `.yml`.
+    #[clap(value_parser)]
+    file: PathBuf,
+}
+
+impl Run {
+    pub async fn process(&self, options: &ExecutorOptions) -> Result<(), OecliError> {
+        let workflow = Workflow::load(&self.file)?;
+        CLIStepExecutor::execute(&workflow, options).await
+    }
+}
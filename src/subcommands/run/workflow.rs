@@ -0,0 +1,596 @@
+//! The format of workflow files and how they are compiled into steps.
+//!
+//! A workflow is a sequence of steps. Steps are either one of the built-in step types, or a
+//! `sequence`, `parallel` or `graph` of further steps. Within a graph, steps name the steps they
+//! depend on with `after`.
+//!
+//! ```toml
+//! title = "Set up service"
+//!
+//! [[steps]]
+//! type = "template-repo"
+//! name = "my-service"
+//! template = "overengineeredone/service-template"
+//!
+//! [[steps]]
+//! type = "graph"
+//! title = "Configure service"
+//!
+//! [[steps.steps]]
+//! id = "clone"
+//! type = "clone"
+//! repo = "my-service"
+//!
+//! [[steps.steps]]
+//! type = "npm-install"
+//! path = "my-service"
+//! after = ["clone"]
+//! ```
+
+use crate::command::CLIStepExecutor;
+use crate::error::OecliError;
+use crate::step::{
+    BoxedStepItem, Condition, ExecutorProperties, FailureMode, ParallelSteps, Step, StepGraph,
+    StepSequence,
+};
+use crate::subcommands::age::{generate_age_key, read_age_public_key};
+use crate::subcommands::filesystem::{copy_file, create_file};
+use crate::subcommands::github::{CloneRepo, CreateTemplateRepo};
+use crate::subcommands::node::NPMInstall;
+use crate::subcommands::shell::{CommandTemplate, ShellStep};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// A pipeline of steps read from a workflow file. The steps at the top level run one after the
+/// other.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workflow {
+    title: String,
+    #[serde(default)]
+    description: String,
+    failure_mode: Option<FailureMode>,
+    steps: Vec<WorkflowStep>,
+}
+
+/// A step of the workflow and the settings every type of step shares.
+#[derive(Clone, Debug, Deserialize)]
+struct WorkflowStep {
+    /// Name the other steps of the same graph refer to in `after`.
+    id: Option<String>,
+    /// Ids of the steps of the same graph that have to complete first.
+    #[serde(default)]
+    after: Vec<String>,
+    /// Only runs the step when the path exists.
+    if_exists: Option<String>,
+    /// Only runs the step when the environment variable is set.
+    if_env: Option<String>,
+    #[serde(flatten)]
+    kind: StepKind,
+}
+
+/// The built-in step types, and the steps that group other steps.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+#[allow(clippy::large_enum_variant)]
+enum StepKind {
+    Sequence {
+        title: String,
+        failure_mode: Option<FailureMode>,
+        steps: Vec<WorkflowStep>,
+    },
+    Parallel {
+        steps: Vec<WorkflowStep>,
+    },
+    Graph {
+        title: String,
+        failure_mode: Option<FailureMode>,
+        steps: Vec<WorkflowStep>,
+    },
+    /// Arguments, `dir`, `env`, `stdin`, `creates` and `unless` can refer to values of earlier
+    /// steps as `{{name}}`.
+    Shell {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        dir: Option<String>,
+        title: Option<String>,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        stdin: Option<String>,
+        /// Exit codes that count as success, `[0]` when not set.
+        success_codes: Option<Vec<i32>>,
+        /// Name the standard output is stored under for later steps.
+        capture: Option<String>,
+        /// Skips the step when the path exists.
+        creates: Option<String>,
+        /// Skips the step when this command, the program followed by its arguments, succeeds.
+        unless: Option<Vec<String>>,
+        /// Command that reverts the step on rollback, the program followed by its arguments.
+        undo: Option<Vec<String>>,
+    },
+    /// Runs a target of the Taskfile in the directory.
+    Task {
+        task: String,
+        dir: Option<String>,
+    },
+    CreateFile {
+        path: String,
+    },
+    CopyFile {
+        from: String,
+        to: String,
+    },
+    TemplateRepo {
+        name: String,
+        template: String,
+        #[serde(default)]
+        public: bool,
+    },
+    Clone {
+        repo: String,
+    },
+    NpmInstall {
+        path: String,
+    },
+    /// Generates the age key of a repository.
+    Age {
+        name: String,
+    },
+    /// Reads the public key of the age key of a repository, for later steps.
+    AgePublicKey {
+        name: String,
+    },
+}
+
+impl Workflow {
+    /// Reads the workflow, the extension of the file decides between TOML and YAML. Checks the
+    /// structure of the workflow, so it can be compiled into steps without errors.
+    pub fn load(path: &Path) -> Result<Workflow, OecliError> {
+        let display = path.display().to_string();
+        let invalid = |msg: String| OecliError::InvalidWorkflow {
+            path: display.clone(),
+            msg,
+        };
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| OecliError::io(&format!("Unable to read {}.", display), e))?;
+        let workflow: Workflow = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?,
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&contents).map_err(|e| invalid(e.to_string()))?
+            }
+            _ => {
+                return Err(invalid(
+                    "Expected a file ending in .toml, .yaml or .yml.".to_string(),
+                ))
+            }
+        };
+        validate_steps(&workflow.steps, "steps", false).map_err(invalid)?;
+        Ok(workflow)
+    }
+
+    fn build(&self) -> Step {
+        let mut sequence = StepSequence::new(&self.title, &self.description);
+        if let Some(failure_mode) = self.failure_mode {
+            sequence = sequence.with_failure_mode(failure_mode);
+        }
+        for step in &self.steps {
+            sequence = sequence.then_run(step.build());
+        }
+        Step::Sequence(sequence)
+    }
+}
+
+#[async_trait]
+impl CLIStepExecutor for Workflow {
+    async fn set_properties(&self, cmd_props: ExecutorProperties) -> ExecutorProperties {
+        cmd_props.then_run(self.build())
+    }
+}
+
+/// Checks that `id` and `after` are only used within a graph, that graphs only contain built-in
+/// steps and that every step of a graph only depends on steps declared before it. `at` describes
+/// where the steps are within the file.
+fn validate_steps(steps: &[WorkflowStep], at: &str, in_graph: bool) -> Result<(), String> {
+    let mut ids = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        let at = format!("{}[{}]", at, i);
+        if !in_graph && (step.id.is_some() || !step.after.is_empty()) {
+            return Err(format!(
+                "{}: `id` and `after` can only be used within a graph.",
+                at
+            ));
+        }
+        if let Some(missing) = step.after.iter().find(|after| !ids.contains(after)) {
+            return Err(format!(
+                "{}: depends on `{}`, which is not declared before it in the graph.",
+                at, missing
+            ));
+        }
+        if let Some(id) = &step.id {
+            if ids.contains(&id) {
+                return Err(format!("{}: the id `{}` is used more than once.", at, id));
+            }
+            ids.push(id);
+        }
+        if let StepKind::Shell { unless, undo, .. } = &step.kind {
+            let empty = |command: &Option<Vec<String>>| command.as_ref().is_some_and(Vec::is_empty);
+            if empty(unless) || empty(undo) {
+                return Err(format!(
+                    "{}: `unless` and `undo` need at least a program.",
+                    at
+                ));
+            }
+        }
+        match &step.kind {
+            StepKind::Sequence { .. } | StepKind::Parallel { .. } | StepKind::Graph { .. }
+                if in_graph =>
+            {
+                return Err(format!(
+                    "{}: a graph can only contain built-in steps, not other groups of steps.",
+                    at
+                ));
+            }
+            StepKind::Sequence { steps, .. } | StepKind::Parallel { steps } => {
+                validate_steps(steps, &format!("{}.steps", at), false)?
+            }
+            StepKind::Graph { steps, .. } => validate_steps(steps, &format!("{}.steps", at), true)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl WorkflowStep {
+    /// The conditions the step is guarded by.
+    fn conditions(&self) -> Vec<Condition> {
+        let exists = self.if_exists.as_deref().map(Condition::path_exists);
+        let env = self.if_env.as_deref().map(Condition::env_set);
+        exists.into_iter().chain(env).collect()
+    }
+
+    fn build(&self) -> Step {
+        if let Some(step_item) = self.build_item() {
+            return Step::Step(step_item);
+        }
+        let step = match &self.kind {
+            StepKind::Sequence {
+                title,
+                failure_mode,
+                steps,
+            } => {
+                let mut sequence = StepSequence::new(title, "");
+                if let Some(failure_mode) = failure_mode {
+                    sequence = sequence.with_failure_mode(*failure_mode);
+                }
+                for step in steps {
+                    sequence = sequence.then_run(step.build());
+                }
+                Step::Sequence(sequence)
+            }
+            StepKind::Parallel { steps } => Step::Parallel(ParallelSteps::new(
+                steps.iter().map(WorkflowStep::build).collect(),
+            )),
+            StepKind::Graph {
+                title,
+                failure_mode,
+                steps,
+            } => {
+                let mut graph = StepGraph::new(title, "");
+                if let Some(failure_mode) = failure_mode {
+                    graph = graph.with_failure_mode(*failure_mode);
+                }
+                let mut ids = HashMap::new();
+                // Validation made sure graphs only contain built-in steps.
+                for step in steps {
+                    if let Some(step_item) = step.build_item() {
+                        let dependencies: Vec<_> = step
+                            .after
+                            .iter()
+                            .filter_map(|after| ids.get(after).copied())
+                            .collect();
+                        let id = graph.add(step_item, &dependencies);
+                        if let Some(name) = &step.id {
+                            ids.insert(name.clone(), id);
+                        }
+                    }
+                }
+                Step::Graph(graph)
+            }
+            _ => unreachable!("built-in steps are returned above"),
+        };
+        self.conditions().into_iter().fold(step, Step::run_if)
+    }
+
+    /// The step item of a built-in step, `None` for groups of steps.
+    fn build_item(&self) -> Option<BoxedStepItem> {
+        let step_item: BoxedStepItem = match &self.kind {
+            StepKind::Sequence { .. } | StepKind::Parallel { .. } | StepKind::Graph { .. } => {
+                return None
+            }
+            StepKind::Shell {
+                program,
+                args,
+                dir,
+                title,
+                env,
+                stdin,
+                success_codes,
+                capture,
+                creates,
+                unless,
+                undo,
+            } => {
+                let mut shell = ShellStep::new(program, args);
+                if let Some(dir) = dir {
+                    shell = shell.current_dir(dir);
+                }
+                if let Some(title) = title {
+                    shell = shell.with_title(title);
+                }
+                for (name, value) in env {
+                    shell = shell.env(name, value);
+                }
+                if let Some(stdin) = stdin {
+                    shell = shell.stdin(stdin);
+                }
+                if let Some(codes) = success_codes {
+                    shell = shell.success_codes(codes);
+                }
+                if let Some(name) = capture {
+                    shell = shell.capture(name);
+                }
+                if let Some(path) = creates {
+                    shell = shell.creates(path);
+                }
+                if let Some(command) = unless.as_deref().and_then(command_template) {
+                    shell = shell.unless(command);
+                }
+                if let Some(command) = undo.as_deref().and_then(command_template) {
+                    shell = shell.undo_with(command);
+                }
+                Box::new(shell)
+            }
+            StepKind::Task { task, dir } => {
+                let mut shell = ShellStep::new("task", std::slice::from_ref(task));
+                if let Some(dir) = dir {
+                    shell = shell.current_dir(dir);
+                }
+                Box::new(shell)
+            }
+            StepKind::CreateFile { path } => Box::new(create_file(path)),
+            StepKind::CopyFile { from, to } => Box::new(copy_file(from, to)),
+            StepKind::TemplateRepo {
+                name,
+                template,
+                public,
+            } => Box::new(CreateTemplateRepo::new(name, template, *public)),
+            StepKind::Clone { repo } => Box::new(CloneRepo::new(repo)),
+            StepKind::NpmInstall { path } => Box::new(NPMInstall::new(path)),
+            StepKind::Age { name } => Box::new(generate_age_key(name)),
+            StepKind::AgePublicKey { name } => Box::new(read_age_public_key(name)),
+        };
+        Some(
+            self.conditions()
+                .into_iter()
+                .fold(step_item, |step_item, condition| condition.guard(step_item)),
+        )
+    }
+}
+
+/// The program followed by its arguments. `None` when the command is empty.
+fn command_template(command: &[String]) -> Option<CommandTemplate> {
+    let (program, args) = command.split_first()?;
+    Some(CommandTemplate::new(program, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::{run_command, FakeOutput, FakeProcessRunner, TempDir};
+    use std::path::PathBuf;
+
+    fn load(dir: &TempDir, name: &str, contents: &str) -> Result<Workflow, OecliError> {
+        dir.write(name, contents);
+        Workflow::load(&Path::new(dir.path()).join(name))
+    }
+
+    /// The message of the error, after checking it names the file.
+    fn invalid(result: Result<Workflow, OecliError>, name: &str) -> String {
+        match result {
+            Err(OecliError::InvalidWorkflow { path, msg }) => {
+                assert!(path.ends_with(name), "{} does not name {}", path, name);
+                msg
+            }
+            Err(e) => panic!("Expected an invalid workflow, got: {}", e),
+            Ok(_) => panic!("Expected an invalid workflow."),
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_graph_steps_that_share_a_title() {
+        let dir = TempDir::new();
+        let workflow = load(
+            &dir,
+            "install.toml",
+            r#"
+title = "Install"
+
+[[steps]]
+type = "graph"
+title = "Packages"
+
+[[steps.steps]]
+id = "web"
+type = "npm-install"
+path = "web"
+
+[[steps.steps]]
+type = "npm-install"
+path = "api"
+after = ["web"]
+"#,
+        )
+        .unwrap();
+        let runner = FakeProcessRunner::new().expect(&["npm", "install"], FakeOutput::success(""));
+
+        run_command(&workflow, &runner).await.unwrap();
+
+        let dirs: Vec<_> = runner
+            .calls()
+            .into_iter()
+            .filter_map(|call| call.current_dir)
+            .collect();
+        assert_eq!(dirs, [PathBuf::from("./web"), PathBuf::from("./api")]);
+    }
+
+    #[tokio::test]
+    async fn skips_steps_whose_condition_is_unmet() {
+        let dir = TempDir::new();
+        let workflow = load(
+            &dir,
+            "release.yaml",
+            r#"
+title: Release
+steps:
+  - type: sequence
+    title: Publish
+    steps:
+      - type: shell
+        program: echo
+        args: [publish]
+        if_env: OECLI_TEST_WORKFLOW_UNSET
+      - type: shell
+        program: echo
+        args: [done]
+"#,
+        )
+        .unwrap();
+        let runner =
+            FakeProcessRunner::new().expect(&["echo", "done"], FakeOutput::success("done"));
+
+        run_command(&workflow, &runner).await.unwrap();
+
+        assert_eq!(runner.argvs(), [["echo", "done"]]);
+    }
+
+    #[test]
+    fn rejects_misspelled_keys() {
+        let dir = TempDir::new();
+        let result = load(
+            &dir,
+            "flow.yml",
+            r#"
+title: Flow
+steps:
+  - type: graph
+    title: Files
+    steps:
+      - id: a
+        type: create-file
+        path: a
+      - type: create-file
+        path: b
+        afer: [a]
+"#,
+        );
+
+        assert!(invalid(result, "flow.yml").contains("unknown field `afer`"));
+    }
+
+    #[test]
+    fn rejects_misspelled_keys_of_the_workflow() {
+        let dir = TempDir::new();
+        let result = load(
+            &dir,
+            "flow.toml",
+            r#"
+title = "Flow"
+failure-mode = "continue"
+steps = []
+"#,
+        );
+
+        assert!(invalid(result, "flow.toml").contains("unknown field `failure-mode`"));
+    }
+
+    #[test]
+    fn rejects_after_outside_a_graph() {
+        let dir = TempDir::new();
+        let result = load(
+            &dir,
+            "flow.toml",
+            r#"
+title = "Flow"
+
+[[steps]]
+type = "create-file"
+path = "a"
+after = ["b"]
+"#,
+        );
+
+        assert_eq!(
+            invalid(result, "flow.toml"),
+            "steps[0]: `id` and `after` can only be used within a graph."
+        );
+    }
+
+    #[test]
+    fn rejects_dependencies_on_later_steps() {
+        let dir = TempDir::new();
+        let result = load(
+            &dir,
+            "flow.yml",
+            r#"
+title: Flow
+steps:
+  - type: graph
+    title: Files
+    steps:
+      - type: create-file
+        path: a
+        after: [b]
+      - id: b
+        type: create-file
+        path: b
+"#,
+        );
+
+        assert_eq!(
+            invalid(result, "flow.yml"),
+            "steps[0].steps[0]: depends on `b`, which is not declared before it in the graph."
+        );
+    }
+
+    #[test]
+    fn rejects_groups_within_a_graph() {
+        let dir = TempDir::new();
+        let result = load(
+            &dir,
+            "flow.yml",
+            r#"
+title: Flow
+steps:
+  - type: graph
+    title: Files
+    steps:
+      - type: parallel
+        steps: []
+"#,
+        );
+
+        assert!(invalid(result, "flow.yml").starts_with("steps[0].steps[0]: a graph can only"));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_toml_or_yaml() {
+        let dir = TempDir::new();
+
+        let msg = invalid(load(&dir, "flow.json", "{}"), "flow.json");
+
+        assert_eq!(msg, "Expected a file ending in .toml, .yaml or .yml.");
+    }
+}
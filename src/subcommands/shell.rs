@@ -0,0 +1,309 @@
+//! Runs an arbitrary program as a step.
+
+use crate::error::OecliError;
+use crate::step::process::{CommandOutput, Invocation};
+use crate::step::{ContextKey, RunContext, ShouldRunResult, StepItem};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// A program and its arguments. Every part can refer to values of the run context as `{{name}}`,
+/// they are filled in right before the program runs.
+#[derive(Clone, Debug)]
+pub struct CommandTemplate {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandTemplate {
+    pub fn new<S: AsRef<str>>(program: &str, args: &[S]) -> CommandTemplate {
+        CommandTemplate {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.as_ref().to_string()).collect(),
+        }
+    }
+
+    /// The command line as it was declared, before any values are filled in.
+    fn display(&self) -> String {
+        Invocation::new(&self.program).args(&self.args).to_string()
+    }
+}
+
+/// Runs a program as a step. The arguments, working directory, environment and standard input are
+/// templates filled in from the [RunContext] when the step runs.
+///
+/// Without `creates` or `unless` the step always runs, it has no way of knowing whether the program
+/// already ran.
+///
+/// Example:
+///
+/// ```
+/// let clone = ShellStep::new("git", &["clone", "{{github.repo_url}}"])
+///     .creates("my-repo")
+///     .undo_with(CommandTemplate::new("rm", &["-rf", "my-repo"]));
+/// ```
+pub struct ShellStep {
+    command: CommandTemplate,
+    /// Directory the program runs in, relative to the current directory.
+    current_dir: Option<String>,
+    env: Vec<(String, String)>,
+    stdin: Option<String>,
+    /// Exit codes that count as success.
+    success_codes: Vec<i32>,
+    /// Name in the context the trimmed standard output is stored under.
+    capture: Option<String>,
+    /// The step is skipped when this path exists.
+    creates: Option<String>,
+    /// The step is skipped when this command exits successfully.
+    unless: Option<CommandTemplate>,
+    undo: Option<CommandTemplate>,
+    resources: Vec<String>,
+    title: Option<String>,
+    description: Option<String>,
+}
+
+impl ShellStep {
+    pub fn new<S: AsRef<str>>(program: &str, args: &[S]) -> ShellStep {
+        ShellStep {
+            command: CommandTemplate::new(program, args),
+            current_dir: None,
+            env: Vec::new(),
+            stdin: None,
+            success_codes: vec![0],
+            capture: None,
+            creates: None,
+            unless: None,
+            undo: None,
+            resources: Vec::new(),
+            title: None,
+            description: None,
+        }
+    }
+
+    pub fn current_dir(mut self, dir: &str) -> ShellStep {
+        self.current_dir = Some(dir.to_string());
+        self
+    }
+
+    /// Sets an environment variable for the program, on top of the environment of oecli.
+    pub fn env(mut self, name: &str, value: &str) -> ShellStep {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Writes the input to the standard input of the program.
+    pub fn stdin(mut self, input: &str) -> ShellStep {
+        self.stdin = Some(input.to_string());
+        self
+    }
+
+    /// Exit codes that count as success, replacing the default of only `0`.
+    pub fn success_codes(mut self, codes: &[i32]) -> ShellStep {
+        self.success_codes = codes.to_vec();
+        self
+    }
+
+    /// Stores the standard output of the program, without surrounding whitespace, in the context
+    /// so later steps can use it.
+    pub fn capture(mut self, name: &str) -> ShellStep {
+        self.capture = Some(name.to_string());
+        self
+    }
+
+    /// Skips the step when the path exists, the program is expected to create it.
+    pub fn creates(mut self, path: &str) -> ShellStep {
+        self.creates = Some(path.to_string());
+        self
+    }
+
+    /// Skips the step when the command exits successfully.
+    pub fn unless(mut self, command: CommandTemplate) -> ShellStep {
+        self.unless = Some(command);
+        self
+    }
+
+    /// The command that reverts the changes of the program when the run rolls back.
+    pub fn undo_with(mut self, command: CommandTemplate) -> ShellStep {
+        self.undo = Some(command);
+        self
+    }
+
+    /// Names of the [resources](crate::step::resource) the program uses.
+    pub fn with_resources(mut self, resources: &[&str]) -> ShellStep {
+        self.resources = resources.iter().map(|r| r.to_string()).collect();
+        self
+    }
+
+    /// Replaces the title, which defaults to the command line.
+    pub fn with_title(mut self, title: &str) -> ShellStep {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn with_description(mut self, description: &str) -> ShellStep {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Fills in the template and sets up the working directory and environment of the step.
+    fn invocation(
+        &self,
+        template: &CommandTemplate,
+        ctx: &RunContext,
+    ) -> Result<Invocation, OecliError> {
+        let mut invocation = Invocation::new(&ctx.render(&template.program)?);
+        for arg in &template.args {
+            invocation = invocation.arg(&ctx.render(arg)?);
+        }
+        if let Some(dir) = &self.current_dir {
+            invocation = invocation.current_dir(&ctx.render(dir)?);
+        }
+        for (name, value) in &self.env {
+            invocation = invocation.env(name, &ctx.render(value)?);
+        }
+        Ok(invocation)
+    }
+
+    fn check_exit(&self, output: &CommandOutput) -> Result<(), OecliError> {
+        match output.code {
+            Some(code) if self.success_codes.contains(&code) => Ok(()),
+            _ => Err(OecliError::exit(output)),
+        }
+    }
+
+    async fn is_done(&self, ctx: &RunContext) -> Result<bool, OecliError> {
+        if let Some(path) = &self.creates {
+            if Path::new(&ctx.render(path)?).exists() {
+                return Ok(true);
+            }
+        }
+        if let Some(unless) = &self.unless {
+            // The directory may only be created by an earlier step.
+            if let Some(dir) = &self.current_dir {
+                if !Path::new(&ctx.render(dir)?).is_dir() {
+                    return Ok(false);
+                }
+            }
+            let output = ctx.run(&self.invocation(unless, ctx)?).await?;
+            return Ok(output.success());
+        }
+        Ok(false)
+    }
+}
+
+#[async_trait]
+impl StepItem for ShellStep {
+    fn title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => format!("Running {}", self.command.display()),
+        }
+    }
+
+    fn description(&self) -> String {
+        if let Some(description) = &self.description {
+            return description.clone();
+        }
+        match &self.current_dir {
+            Some(dir) => format!("Runs `{}` in {}.", self.command.display(), dir),
+            None => format!("Runs `{}`.", self.command.display()),
+        }
+    }
+
+    /// Skips the step when the path it creates exists, or the `unless` command succeeds. Values
+    /// the templates refer to may not be known before the earlier steps ran, then the step runs.
+    async fn should_run(&self, ctx: &RunContext) -> ShouldRunResult {
+        match self.is_done(ctx).await {
+            Ok(true) => ShouldRunResult::Skip,
+            Ok(false) | Err(OecliError::Unset(_)) => ShouldRunResult::Ok,
+            Err(e) => ShouldRunResult::Error(e),
+        }
+    }
+
+    async fn execute(&self, ctx: &RunContext) -> Result<String, OecliError> {
+        let mut invocation = self.invocation(&self.command, ctx)?;
+        if let Some(stdin) = &self.stdin {
+            invocation = invocation.stdin(&ctx.render(stdin)?);
+        }
+        let output = ctx.run(&invocation).await?;
+        self.check_exit(&output)?;
+        if let Some(name) = &self.capture {
+            ctx.set(&ContextKey::named(name), output.stdout.trim().to_string())?;
+        }
+        Ok(format!(
+            "Ran `{}` ({:.1}s).",
+            output.command,
+            output.duration.as_secs_f32()
+        ))
+    }
+
+    async fn undo(&self, ctx: &RunContext) -> Option<Result<String, OecliError>> {
+        let undo = self.undo.as_ref()?;
+        let result = async {
+            let output = ctx.run(&self.invocation(undo, ctx)?).await?;
+            self.check_exit(&output)?;
+            Ok(format!("Ran `{}`.", output.command))
+        };
+        Some(result.await)
+    }
+
+    fn resources(&self) -> Vec<String> {
+        self.resources.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::testing::{FakeOutput, FakeProcessRunner};
+
+    #[tokio::test]
+    async fn captures_the_trimmed_output() {
+        let runner =
+            FakeProcessRunner::new().expect(&["echo", "hello"], FakeOutput::success("hello\n"));
+        let ctx = runner.context();
+        ctx.set(&ContextKey::named("word"), "hello".to_string())
+            .unwrap();
+        let step = ShellStep::new("echo", &["{{word}}"])
+            .env("GREETING", "{{word}}")
+            .capture("echoed");
+
+        assert!(step.execute(&ctx).await.is_ok());
+        assert_eq!(
+            ctx.get(&ContextKey::<String>::named("echoed")).unwrap(),
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            runner.calls()[0].env,
+            vec![("GREETING".to_string(), "hello".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_when_the_unless_command_succeeds() {
+        let runner = FakeProcessRunner::new().expect(&["which", "tool"], FakeOutput::success(""));
+        let step =
+            ShellStep::new("make", &["install"]).unless(CommandTemplate::new("which", &["tool"]));
+
+        assert!(matches!(
+            step.should_run(&runner.context()).await,
+            ShouldRunResult::Skip
+        ));
+        assert_eq!(runner.argvs(), vec![vec!["which", "tool"]]);
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_exit_code_that_is_not_a_success() {
+        let runner = FakeProcessRunner::new()
+            .expect(&["make", "install"], FakeOutput::failure(2, "no rule"))
+            .expect(&["make", "check"], FakeOutput::failure(1, ""));
+        let ctx = runner.context();
+
+        let install = ShellStep::new("make", &["install"]).execute(&ctx).await;
+        assert!(matches!(install, Err(OecliError::Exit { .. })));
+        let check = ShellStep::new("make", &["check"])
+            .success_codes(&[0, 1])
+            .execute(&ctx)
+            .await;
+        assert!(check.is_ok());
+    }
+}
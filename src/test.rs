@@ -1,36 +1,13 @@
 //! Optional cargo features enables this Subcommand. This subcommand is used for testing and
-//! development purposes only.
+//! development purposes only. The executor itself is tested with fake steps, see
+//! `step::executor::tests`.
 
 use crate::command::CLIStepExecutor;
-use crate::step::event::{EventData, NewSequenceEvent};
-use crate::step::msg_handler::EventHandler;
-use crate::step::{ExecutorProperties, ShouldRunResult, Step, StepDetails, StepItem, StepSequence};
+use crate::error::OecliError;
+use crate::step::{ExecutorProperties, RunContext, ShouldRunResult, Step, StepItem, StepSequence};
 use async_trait::async_trait;
 use clap::Args;
 
-/// Since the Event Handler is behind a lock, and we are intentionally timing out the threads, we can
-/// deterministically test the timing of the messages to test the executor. This should move out
-/// and under Event Handler behind the "tests" feature.
-pub struct TestEvent {
-    cb: fn(msg: String),
-}
-
-impl EventHandler for TestEvent {
-    fn sequence_start(&mut self, details: NewSequenceEvent) {}
-    fn sequence_end(&mut self, details: &EventData) {}
-    fn step_start(&mut self, step: StepDetails, data: &EventData) {}
-    fn step_end(&mut self, step: StepDetails, data: &EventData) {}
-    fn step_skipped(&mut self, step: StepDetails, data: &EventData) {}
-    fn step_error(&mut self, step: StepDetails, data: &EventData) {}
-}
-
-impl TestEvent {
-    #[allow(dead_code)]
-    pub fn new(cb: fn(msg: String)) -> TestEvent {
-        TestEvent { cb }
-    }
-}
-
 #[derive(Args, Clone, Debug)]
 pub struct Test {
     /// Name of cloud, will become the git repository name.
@@ -62,11 +39,11 @@ impl StepItem for TestStep {
         format!("Will sleep for {}ms", self.delay)
     }
 
-    async fn should_run(&self) -> ShouldRunResult {
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
         ShouldRunResult::Ok
     }
 
-    async fn execute(self: Box<Self>) -> Result<String, String> {
+    async fn execute(&self, _ctx: &RunContext) -> Result<String, OecliError> {
         tokio::time::sleep(tokio::time::Duration::from_millis(self.delay as u64)).await;
         Ok("".to_string())
     }
@@ -93,11 +70,11 @@ impl StepItem for TestStep2 {
         format!("Will sleep for {}ms", self.delay)
     }
 
-    async fn should_run(&self) -> ShouldRunResult {
+    async fn should_run(&self, _ctx: &RunContext) -> ShouldRunResult {
         ShouldRunResult::Ok
     }
 
-    async fn execute(self: Box<Self>) -> Result<String, String> {
+    async fn execute(&self, _ctx: &RunContext) -> Result<String, OecliError> {
         tokio::time::sleep(tokio::time::Duration::from_millis(self.delay as u64)).await;
         Ok("".to_string())
     }